@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use opengamesir::capture::{pcap, usb};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(packets) = pcap::read(data) else {
+        return;
+    };
+    for packet in &packets {
+        let _ = usb::decode(packet);
+    }
+});
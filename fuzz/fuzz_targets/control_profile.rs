@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use opengamesir::driver::{ControlProfile, Layout, ProfileKind, registry};
+
+fuzz_target!(|data: &[u8]| {
+    let kind = ProfileKind::Control;
+    let _ = kind.validate(data);
+    if data.len() == kind.size() {
+        let _ = registry::diff(kind.shape(), kind.size(), &vec![0; kind.size()], data);
+    }
+
+    let Ok(profile) = ControlProfile::read(&mut &data[..]) else {
+        return;
+    };
+
+    // Reserved bytes aren't kept, but encoding what was decoded must be
+    // stable from then on
+    let mut bytes = Vec::new();
+    profile.write(&mut bytes).unwrap();
+    assert_eq!(bytes.len(), ControlProfile::SIZE);
+
+    let decoded = ControlProfile::read(&mut bytes.as_slice()).unwrap();
+    let mut again = Vec::new();
+    decoded.write(&mut again).unwrap();
+    assert_eq!(bytes, again);
+});
@@ -0,0 +1,134 @@
+#![no_main]
+
+//! Drives the simulated controller through `Cyclone2` with random sequences
+//! of operations, checking that the driver's view of each profile matches
+//! what the model holds.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use opengamesir::driver::simulator::Simulator;
+use opengamesir::driver::{Cyclone2, ProfileId, ProfileKind, Variant, registry};
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Read { profile: u8 },
+    Write { profile: u8, offset: u16, bytes: Vec<u8> },
+    Switch { profile: u8 },
+    CurrentProfile,
+    FirmwareVersion,
+    Vibrate { left: u8, right: u8 },
+    Copy { from: u8, to: u8 },
+    Swap { a: u8, b: u8 },
+    Raw { report: Vec<u8> },
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let control = ProfileKind::Control;
+    let shift_value = registry::resolve(control.shape(), control.size(), "shift_value").unwrap();
+
+    let simulator = Simulator::new();
+    let c2 = Cyclone2::with_transport(Box::new(simulator.clone()), Variant::Wired);
+
+    // What each profile should hold, as far as the driver knows
+    let mut expected: HashMap<ProfileId, Vec<u8>> = ProfileId::ALL
+        .into_iter()
+        .map(|id| (id, simulator.profile(id)))
+        .collect();
+
+    for op in ops {
+        match op {
+            Op::Read { profile } => {
+                let Some(id) = ProfileId::from_index(profile) else {
+                    continue;
+                };
+                let bytes = c2.read_profile(id, id.kind().size()).unwrap();
+                assert_eq!(bytes, expected[&id]);
+            }
+            Op::Write {
+                profile,
+                offset,
+                bytes,
+            } => {
+                let Some(id) = ProfileId::from_index(profile) else {
+                    continue;
+                };
+                let offset = offset as usize;
+                let fits = offset + bytes.len() <= id.kind().size();
+                let res = c2.write_profile_range(id, offset, &bytes);
+                assert_eq!(res.is_ok(), fits, "{res:?}");
+                if fits {
+                    expected.get_mut(&id).unwrap()[offset..offset + bytes.len()]
+                        .copy_from_slice(&bytes);
+                }
+            }
+            Op::Switch { profile } => {
+                let Some(id) = ProfileId::from_index(profile) else {
+                    continue;
+                };
+                let res = c2.switch_profile(id);
+                assert_eq!(res.is_ok(), id != ProfileId::Light);
+                if res.is_ok() {
+                    assert_eq!(c2.get_current_profile().unwrap(), id);
+                }
+            }
+            Op::CurrentProfile => {
+                let id = c2.get_current_profile().unwrap();
+                assert_eq!(id.index(), simulator.current_profile().max(1));
+            }
+            Op::FirmwareVersion => {
+                c2.get_firmware_version().unwrap();
+            }
+            Op::Vibrate { left, right } => {
+                c2.vibrate(left, right).unwrap();
+                assert_eq!(simulator.vibration(), (left, right));
+            }
+            Op::Copy { from, to } => {
+                let (Some(from), Some(to)) = (ProfileId::from_index(from), ProfileId::from_index(to))
+                else {
+                    continue;
+                };
+                if c2.copy_profile(from, to, None).is_ok() {
+                    let copied = expected[&from].clone();
+                    expected.insert(to, copied);
+                }
+            }
+            Op::Swap { a, b } => {
+                let (Some(a), Some(b)) = (ProfileId::from_index(a), ProfileId::from_index(b)) else {
+                    continue;
+                };
+                let before = expected.clone();
+                if c2.swap_profiles(a, b).is_ok() {
+                    // Retargeting shift_value can change any control
+                    // profile, so take the model's word for it
+                    for id in ProfileId::ALL {
+                        expected.insert(id, simulator.profile(id));
+                    }
+                    let without_shift = |id: ProfileId, profiles: &HashMap<_, Vec<u8>>| {
+                        let mut bytes = profiles[&id].clone();
+                        bytes[shift_value.offset] = 0;
+                        bytes
+                    };
+                    assert_eq!(without_shift(a, &expected), without_shift(b, &before));
+                    assert_eq!(without_shift(b, &expected), without_shift(a, &before));
+                }
+            }
+            Op::Raw { report } => {
+                c2.write_raw(&report).unwrap();
+                // Raw writes bypass the driver, so resynchronise, and drop
+                // any replies so they aren't taken for the next command's
+                while c2.read_raw(Duration::ZERO).unwrap().is_some() {}
+                for id in ProfileId::ALL {
+                    expected.insert(id, simulator.profile(id));
+                }
+            }
+        }
+
+        for id in ProfileId::ALL {
+            assert_eq!(simulator.profile(id), expected[&id], "profile {id} diverged");
+        }
+        assert!(!simulator.has_pending_replies());
+    }
+});
@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use opengamesir::capture::ProfileTracker;
+use opengamesir::driver::protocol::{self, INPUT_FIELDS};
+
+// Reports as they come from the controller, or from a capture
+fuzz_target!(|reports: Vec<Vec<u8>>| {
+    let mut tracker = ProfileTracker::new();
+    for report in &reports {
+        let _ = protocol::annotate(report);
+        for field in INPUT_FIELDS {
+            let _ = field.read(report);
+        }
+        for transfer in tracker.push(report) {
+            let _ = transfer;
+        }
+    }
+    let _ = tracker.finish();
+});
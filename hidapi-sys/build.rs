@@ -1,22 +1,98 @@
-use std::env;
 use std::path::PathBuf;
 
 fn main() {
-    let bindings = bindgen::builder()
-        .header("../external/hidapi/hidapi/hidapi.h")
-        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
-        .generate()
-        .expect("Unable to generate bindings");
+    // A system library takes precedence, so that it can be chosen without
+    // turning off the default features of every crate depending on this one
+    let include_paths = if cfg!(feature = "system-libusb") {
+        system("hidapi-libusb")
+    } else if cfg!(feature = "system-hidraw") {
+        system("hidapi-hidraw")
+    } else {
+        vendored()
+    };
 
-    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
-    bindings
-        .write_to_file(out_path.join("bindings.rs"))
-        .expect("Couldn't write bindings!");
+    #[cfg(feature = "bindgen")]
+    generate_bindings(&include_paths);
+    #[cfg(not(feature = "bindgen"))]
+    let _ = include_paths;
+}
+
+/// Links against an installed hidapi, returning its header directories.
+fn system(name: &str) -> Vec<PathBuf> {
+    let library = pkg_config::Config::new()
+        // hid_read_error is new in 0.15
+        .atleast_version("0.15")
+        .probe(name)
+        .unwrap_or_else(|e| panic!("Couldn't find {name}: {e}"));
+    #[cfg(not(feature = "bindgen"))]
+    check_bindings_version(&library.version);
+    library.include_paths
+}
+
+/// Checks that the pregenerated bindings are for the installed hidapi's
+/// version, as its structs can change between versions.
+#[cfg(not(feature = "bindgen"))]
+fn check_bindings_version(installed: &str) {
+    println!("cargo:rerun-if-changed=src/bindings.rs");
+    let bindings = include_str!("src/bindings.rs");
+    let constant = |name: &str| {
+        let prefix = format!("pub const HID_API_VERSION_{name}: u32 = ");
+        bindings
+            .lines()
+            .find_map(|line| line.strip_prefix(&prefix)?.strip_suffix(';'))
+            .unwrap_or_else(|| panic!("src/bindings.rs has no HID_API_VERSION_{name}"))
+    };
+    let bindings_version = format!("{}.{}", constant("MAJOR"), constant("MINOR"));
 
+    let installed_version = installed.split('.').take(2).collect::<Vec<_>>().join(".");
+    if installed_version != bindings_version {
+        panic!(
+            "Found hidapi {installed}, but the pregenerated bindings are for \
+             {bindings_version}. Enable the `bindgen` feature to generate \
+             bindings for it."
+        );
+    }
+}
+
+#[cfg(feature = "vendored")]
+fn vendored() -> Vec<PathBuf> {
     cc::Build::new()
         .file("../external/hidapi/linux/hid.c")
         .include("../external/hidapi/hidapi")
         .compile("libhidapi.a");
+    println!("cargo:rerun-if-changed=../external/hidapi/linux/hid.c");
 
     pkg_config::probe_library("libudev").unwrap();
+
+    vec![PathBuf::from("../external/hidapi/hidapi")]
+}
+
+#[cfg(not(feature = "vendored"))]
+fn vendored() -> Vec<PathBuf> {
+    panic!("One of the `vendored`, `system-hidraw` or `system-libusb` features must be enabled");
+}
+
+#[cfg(feature = "bindgen")]
+fn generate_bindings(include_paths: &[PathBuf]) {
+    let header = include_paths
+        .iter()
+        .map(|path| path.join("hidapi.h"))
+        .find(|header| header.exists())
+        .expect("Couldn't find hidapi.h");
+
+    let bindings = bindgen::builder()
+        .header(header.to_string_lossy())
+        .allowlist_function("hid_.*")
+        .allowlist_type("hid_.*")
+        .allowlist_var("HID_API_.*")
+        .generate_comments(false)
+        .layout_tests(false)
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
+        .generate()
+        .expect("Unable to generate bindings");
+
+    let out_path = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    bindings
+        .write_to_file(out_path.join("bindings.rs"))
+        .expect("Couldn't write bindings!");
 }
@@ -0,0 +1,193 @@
+// Bindings to hidapi.h from hidapi 0.15, kept by hand. They started as
+// bindgen's output with the allowlist and options in build.rs, which the
+// `bindgen` feature generates from the header instead.
+//
+// bindgen gives the `wchar_t` of the target it ran for, so the definitions
+// below were written by hand to cover every target. When updating hidapi,
+// generate new bindings and copy them from the build's OUT_DIR, then put
+// back the `wchar_t` definitions and update HID_API_VERSION_*, which
+// build.rs checks against an installed hidapi.
+
+pub const HID_API_VERSION_MAJOR: u32 = 0;
+pub const HID_API_VERSION_MINOR: u32 = 15;
+pub const HID_API_VERSION_PATCH: u32 = 0;
+pub const HID_API_MAX_REPORT_DESCRIPTOR_SIZE: u32 = 4096;
+#[cfg(windows)]
+pub type wchar_t = u16;
+#[cfg(all(target_os = "linux", any(target_arch = "aarch64", target_arch = "arm")))]
+pub type wchar_t = ::std::os::raw::c_uint;
+#[cfg(not(any(
+    windows,
+    all(target_os = "linux", any(target_arch = "aarch64", target_arch = "arm"))
+)))]
+pub type wchar_t = ::std::os::raw::c_int;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct hid_api_version {
+    pub major: ::std::os::raw::c_int,
+    pub minor: ::std::os::raw::c_int,
+    pub patch: ::std::os::raw::c_int,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct hid_device_ {
+    _unused: [u8; 0],
+}
+pub type hid_device = hid_device_;
+pub const hid_bus_type_HID_API_BUS_UNKNOWN: hid_bus_type = 0;
+pub const hid_bus_type_HID_API_BUS_USB: hid_bus_type = 1;
+pub const hid_bus_type_HID_API_BUS_BLUETOOTH: hid_bus_type = 2;
+pub const hid_bus_type_HID_API_BUS_I2C: hid_bus_type = 3;
+pub const hid_bus_type_HID_API_BUS_SPI: hid_bus_type = 4;
+pub type hid_bus_type = ::std::os::raw::c_uint;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct hid_device_info {
+    pub path: *mut ::std::os::raw::c_char,
+    pub vendor_id: ::std::os::raw::c_ushort,
+    pub product_id: ::std::os::raw::c_ushort,
+    pub serial_number: *mut wchar_t,
+    pub release_number: ::std::os::raw::c_ushort,
+    pub manufacturer_string: *mut wchar_t,
+    pub product_string: *mut wchar_t,
+    pub usage_page: ::std::os::raw::c_ushort,
+    pub usage: ::std::os::raw::c_ushort,
+    pub interface_number: ::std::os::raw::c_int,
+    pub next: *mut hid_device_info,
+    pub bus_type: hid_bus_type,
+}
+unsafe extern "C" {
+    pub fn hid_init() -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_exit() -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_enumerate(
+        vendor_id: ::std::os::raw::c_ushort,
+        product_id: ::std::os::raw::c_ushort,
+    ) -> *mut hid_device_info;
+}
+unsafe extern "C" {
+    pub fn hid_free_enumeration(devs: *mut hid_device_info);
+}
+unsafe extern "C" {
+    pub fn hid_open(
+        vendor_id: ::std::os::raw::c_ushort,
+        product_id: ::std::os::raw::c_ushort,
+        serial_number: *const wchar_t,
+    ) -> *mut hid_device;
+}
+unsafe extern "C" {
+    pub fn hid_open_path(path: *const ::std::os::raw::c_char) -> *mut hid_device;
+}
+unsafe extern "C" {
+    pub fn hid_write(
+        dev: *mut hid_device,
+        data: *const ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_read_timeout(
+        dev: *mut hid_device,
+        data: *mut ::std::os::raw::c_uchar,
+        length: usize,
+        milliseconds: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_read(
+        dev: *mut hid_device,
+        data: *mut ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_set_nonblocking(
+        dev: *mut hid_device,
+        nonblock: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_send_feature_report(
+        dev: *mut hid_device,
+        data: *const ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_get_feature_report(
+        dev: *mut hid_device,
+        data: *mut ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_send_output_report(
+        dev: *mut hid_device,
+        data: *const ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_get_input_report(
+        dev: *mut hid_device,
+        data: *mut ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_close(dev: *mut hid_device);
+}
+unsafe extern "C" {
+    pub fn hid_get_manufacturer_string(
+        dev: *mut hid_device,
+        string: *mut wchar_t,
+        maxlen: usize,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_get_product_string(
+        dev: *mut hid_device,
+        string: *mut wchar_t,
+        maxlen: usize,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_get_serial_number_string(
+        dev: *mut hid_device,
+        string: *mut wchar_t,
+        maxlen: usize,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_get_device_info(dev: *mut hid_device) -> *mut hid_device_info;
+}
+unsafe extern "C" {
+    pub fn hid_get_indexed_string(
+        dev: *mut hid_device,
+        string_index: ::std::os::raw::c_int,
+        string: *mut wchar_t,
+        maxlen: usize,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_get_report_descriptor(
+        dev: *mut hid_device,
+        buf: *mut ::std::os::raw::c_uchar,
+        buf_size: usize,
+    ) -> ::std::os::raw::c_int;
+}
+unsafe extern "C" {
+    pub fn hid_error(dev: *mut hid_device) -> *const wchar_t;
+}
+unsafe extern "C" {
+    pub fn hid_version() -> *const hid_api_version;
+}
+unsafe extern "C" {
+    pub fn hid_version_str() -> *const ::std::os::raw::c_char;
+}
+unsafe extern "C" {
+    pub fn hid_read_error(dev: *mut hid_device) -> *const wchar_t;
+}
@@ -0,0 +1,467 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Expr, Fields, LitInt, Path, parse_macro_input};
+
+/// Derives `opengamesir::driver::Layout` for a struct or an enum of byte
+/// values.
+///
+/// Struct fields are encoded in declaration order. Supported field
+/// attributes:
+///
+/// - `#[layout(pad_before = N)]`: the field is preceded by `N` reserved bytes,
+///   which are skipped on read and zeroed on write.
+/// - `#[layout(string = N)]`: the field is a `String` stored in `N` null-padded
+///   bytes.
+/// - `#[layout(trim_last = N)]`: the field is an array whose final element is
+///   stored without its last `N` bytes.
+/// - `#[layout(labels = EXPR)]`: the field is an array whose elements are named
+///   by `EXPR`, a `&'static [&'static str]` with one label per element.
+/// - `#[layout(flatten)]`: the field is a struct whose own fields can be
+///   addressed by path as if they belonged to this one, e.g.
+///   `left_stick.front_dead` for `left_stick.dead_module.front_dead`.
+/// - `#[layout(reserved)]`: the field's bytes are kept but have no known
+///   purpose. Diffs and dumps mark it as reserved.
+/// - `#[layout(unknown)]`: the field's bytes are set by the controller or the
+///   app, but what they do isn't known. Diffs and dumps mark it as unknown.
+///
+/// `reserved` and `unknown` set the field's
+/// `opengamesir::driver::layout::FieldKind`; fields without either are
+/// `Known`.
+///
+/// Structs may also be annotated with `#[layout(validate = path)]`, where
+/// `path` is a `fn(&Self) -> eyre::Result<()>` that is run after reading.
+///
+/// Structs also get `<Name>View<'a>` and `<Name>ViewMut<'a>` types with an
+/// accessor per field, which read and write the encoded bytes in place.
+///
+/// Enums must be `#[repr(u8)]` with an explicit discriminant for every
+/// variant. They also get `Display` and `FromStr` implementations using the
+/// variant names, and a conversion to `u8`. One variant holding a `u8` may be
+/// marked `#[layout(other)]`, to keep bytes that no other variant matches
+/// rather than failing to read them. Such bytes are still rejected when
+/// parsed from text.
+#[proc_macro_derive(Layout, attributes(layout))]
+pub fn derive_layout(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let res = match &input.data {
+        Data::Struct(data) => derive_struct(&input, &data.fields),
+        Data::Enum(data) => derive_enum(&input, data),
+        Data::Union(_) => Err(syn::Error::new(input.span(), "unions are not supported")),
+    };
+    res.unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    pad_before: Option<LitInt>,
+    string: Option<LitInt>,
+    trim_last: Option<LitInt>,
+    labels: Option<Expr>,
+    flatten: bool,
+    kind: Option<proc_macro2::Ident>,
+}
+
+fn parse_field_attrs(field: &syn::Field) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs::default();
+    for attr in &field.attrs {
+        if !attr.path().is_ident("layout") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("flatten") {
+                attrs.flatten = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("reserved") {
+                attrs.kind = Some(format_ident!("Reserved"));
+                return Ok(());
+            }
+            if meta.path.is_ident("unknown") {
+                attrs.kind = Some(format_ident!("Unknown"));
+                return Ok(());
+            }
+            if meta.path.is_ident("labels") {
+                attrs.labels = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            let slot = if meta.path.is_ident("pad_before") {
+                &mut attrs.pad_before
+            } else if meta.path.is_ident("string") {
+                &mut attrs.string
+            } else if meta.path.is_ident("trim_last") {
+                &mut attrs.trim_last
+            } else {
+                return Err(meta.error("unknown layout attribute"));
+            };
+            *slot = Some(meta.value()?.parse()?);
+            Ok(())
+        })?;
+    }
+    if attrs.string.is_some() && attrs.trim_last.is_some() {
+        return Err(syn::Error::new(
+            field.span(),
+            "`string` and `trim_last` cannot be combined",
+        ));
+    }
+    Ok(attrs)
+}
+
+fn parse_validate(input: &DeriveInput) -> syn::Result<Option<Path>> {
+    let mut validate = None;
+    for attr in &input.attrs {
+        if !attr.path().is_ident("layout") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("validate") {
+                validate = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown layout attribute"))
+            }
+        })?;
+    }
+    Ok(validate)
+}
+
+fn derive_struct(input: &DeriveInput, fields: &Fields) -> syn::Result<TokenStream> {
+    let Fields::Named(fields) = fields else {
+        return Err(syn::Error::new(
+            fields.span(),
+            "only structs with named fields are supported",
+        ));
+    };
+
+    let name = &input.ident;
+    let vis = &input.vis;
+    let view = format_ident!("{name}View");
+    let view_mut = format_ident!("{name}ViewMut");
+    let validate = parse_validate(input)?;
+
+    let mut offset = quote!(0);
+    let mut field_infos = Vec::new();
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    let mut idents = Vec::new();
+    let mut accessors = Vec::new();
+    let mut accessors_mut = Vec::new();
+
+    for field in &fields.named {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let attrs = parse_field_attrs(field)?;
+
+        if let Some(pad) = &attrs.pad_before {
+            offset = quote!(#offset + #pad);
+            reads.push(quote!(layout::skip(reader, #pad)?;));
+            writes.push(quote!(layout::zeros(writer, #pad)?;));
+        }
+
+        let (size, shape, read, write) = if let Some(len) = &attrs.string {
+            (
+                quote!(#len),
+                quote!(&layout::Shape::String),
+                quote!(layout::read_string(reader, #len)?),
+                quote!(layout::write_string(&self.#ident, writer, #len)?),
+            )
+        } else if let Some(trim) = &attrs.trim_last {
+            (
+                quote!(<#ty as layout::Layout>::SIZE - #trim),
+                quote!(<#ty as layout::Layout>::SHAPE),
+                quote!(layout::read_trimmed(reader, #trim)?),
+                quote!(layout::write_trimmed(&self.#ident, writer, #trim)?),
+            )
+        } else {
+            (
+                quote!(<#ty as layout::Layout>::SIZE),
+                quote!(<#ty as layout::Layout>::SHAPE),
+                quote!(<#ty as layout::Layout>::read(reader)?),
+                quote!(layout::Layout::write(&self.#ident, writer)?),
+            )
+        };
+
+        let shape = match &attrs.labels {
+            Some(labels) => quote!(&layout::Shape::labelled(#shape, #labels)),
+            None => shape,
+        };
+
+        let bytes = quote!(layout::sub(self.bytes, #offset, #size));
+        let bytes_mut = quote!(layout::sub_mut(self.bytes, #offset, #size));
+        if attrs.string.is_some() {
+            accessors.push(quote! {
+                pub fn #ident(&self) -> layout::Str<'a> {
+                    layout::Str::new(#bytes)
+                }
+            });
+            accessors_mut.push(quote! {
+                pub fn #ident(&mut self) -> layout::StrMut<'_> {
+                    layout::StrMut::new(#bytes_mut)
+                }
+            });
+        } else {
+            accessors.push(quote! {
+                pub fn #ident(&self) -> <#ty as layout::Layout>::View<'a> {
+                    <#ty as layout::Layout>::view(#bytes)
+                }
+            });
+            accessors_mut.push(quote! {
+                pub fn #ident(&mut self) -> <#ty as layout::Layout>::ViewMut<'_> {
+                    <#ty as layout::Layout>::view_mut(#bytes_mut)
+                }
+            });
+        }
+
+        let field_name = ident.to_string();
+        let flatten = attrs.flatten;
+        let kind = attrs.kind.unwrap_or_else(|| format_ident!("Known"));
+        field_infos.push(quote! {
+            layout::Field {
+                name: #field_name,
+                offset: #offset,
+                size: #size,
+                shape: #shape,
+                flatten: #flatten,
+                kind: layout::FieldKind::#kind,
+            }
+        });
+        reads.push(quote!(let #ident = #read;));
+        writes.push(quote!(#write;));
+        idents.push(ident);
+
+        offset = quote!(#offset + #size);
+    }
+
+    let validate = validate.map(|path| quote!(#path(&value)?;));
+
+    Ok(quote! {
+        #vis struct #view<'a> {
+            bytes: &'a [u8],
+        }
+
+        #vis struct #view_mut<'a> {
+            bytes: &'a mut [u8],
+        }
+
+        const _: () = {
+            use ::opengamesir::driver::layout;
+
+            impl<'a> #view<'a> {
+                pub fn bytes(&self) -> &'a [u8] {
+                    self.bytes
+                }
+
+                pub fn get(&self) -> ::eyre::Result<#name> {
+                    <#name as layout::Layout>::read(&mut &*self.bytes)
+                }
+
+                #(#accessors)*
+            }
+
+            impl<'a> #view_mut<'a> {
+                pub fn bytes(&self) -> &[u8] {
+                    self.bytes
+                }
+
+                pub fn as_view(&self) -> #view<'_> {
+                    #view { bytes: self.bytes }
+                }
+
+                pub fn get(&self) -> ::eyre::Result<#name> {
+                    <#name as layout::Layout>::read(&mut &*self.bytes)
+                }
+
+                pub fn set(&mut self, value: &#name) -> ::eyre::Result<()> {
+                    layout::set_bytes(
+                        self.bytes,
+                        <#name as layout::Layout>::SIZE,
+                        |writer| layout::Layout::write(value, writer),
+                    )
+                }
+
+                #(#accessors_mut)*
+            }
+
+            impl layout::Layout for #name {
+                const SIZE: usize = #offset;
+
+                const FIELDS: &'static [layout::Field] = &[#(#field_infos),*];
+
+                const SHAPE: &'static layout::Shape =
+                    &layout::Shape::Struct(<Self as layout::Layout>::FIELDS);
+
+                type View<'a> = #view<'a>;
+                type ViewMut<'a> = #view_mut<'a>;
+
+                fn view(bytes: &[u8]) -> #view<'_> {
+                    #view { bytes }
+                }
+
+                fn view_mut(bytes: &mut [u8]) -> #view_mut<'_> {
+                    #view_mut { bytes }
+                }
+
+                fn read(reader: &mut impl ::std::io::Read) -> ::eyre::Result<Self> {
+                    #(#reads)*
+                    let value = #name { #(#idents),* };
+                    #validate
+                    Ok(value)
+                }
+
+                fn write(&self, writer: &mut impl ::std::io::Write) -> ::eyre::Result<()> {
+                    #(#writes)*
+                    Ok(())
+                }
+            }
+        };
+    })
+}
+
+fn derive_enum(input: &DeriveInput, data: &syn::DataEnum) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let description = name.to_string();
+
+    let mut known_arms = Vec::new();
+    let mut write_arms = Vec::new();
+    let mut display_arms = Vec::new();
+    let mut parse_arms = Vec::new();
+    let mut other = None;
+
+    for variant in &data.variants {
+        let ident = &variant.ident;
+        if is_other(variant)? {
+            if other.is_some() {
+                return Err(syn::Error::new(
+                    variant.span(),
+                    "only one variant can be `other`",
+                ));
+            }
+            if !matches!(&variant.fields, Fields::Unnamed(fields) if fields.unnamed.len() == 1) {
+                return Err(syn::Error::new(
+                    variant.span(),
+                    "the `other` variant must hold a single `u8`",
+                ));
+            }
+            write_arms.push(quote!(#name::#ident(value) => *value,));
+            display_arms.push(quote!(#name::#ident(value) => write!(f, "{value}"),));
+            other = Some(ident);
+            continue;
+        }
+
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new(
+                variant.span(),
+                "only fieldless variants are supported, other than the `other` variant",
+            ));
+        }
+        let Some((_, discriminant)) = &variant.discriminant else {
+            return Err(syn::Error::new(
+                variant.span(),
+                "every variant needs an explicit discriminant",
+            ));
+        };
+        let discriminant: &Expr = discriminant;
+        let name_str = ident.to_string();
+        known_arms.push(quote!(#discriminant => #name::#ident,));
+        write_arms.push(quote!(#name::#ident => #discriminant,));
+        display_arms.push(quote!(#name::#ident => f.write_str(#name_str),));
+        parse_arms.push(quote!(s if s.eq_ignore_ascii_case(#name_str) => #name::#ident,));
+    }
+
+    // Bytes read from a controller are kept as they are, but text parsed from
+    // the user must name a known value
+    let fallback_arm = match other {
+        Some(other) => quote!(value => #name::#other(value),),
+        None => quote!(_ => ::eyre::bail!("invalid {}: {value}", #description),),
+    };
+
+    Ok(quote! {
+        const _: () = {
+            use ::opengamesir::driver::layout;
+
+            impl ::std::fmt::Display for #name {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                    match self {
+                        #(#display_arms)*
+                    }
+                }
+            }
+
+            impl ::std::str::FromStr for #name {
+                type Err = ::eyre::Report;
+
+                fn from_str(s: &str) -> ::eyre::Result<Self> {
+                    if let Ok(value) = s.parse::<u8>() {
+                        return Ok(match value {
+                            #(#known_arms)*
+                            _ => ::eyre::bail!("invalid {}: {s}", #description),
+                        });
+                    }
+                    Ok(match s {
+                        #(#parse_arms)*
+                        _ => ::eyre::bail!("invalid {}: {s}", #description),
+                    })
+                }
+            }
+
+            impl ::std::convert::From<#name> for u8 {
+                fn from(value: #name) -> u8 {
+                    match &value {
+                        #(#write_arms)*
+                    }
+                }
+            }
+
+            impl layout::Layout for #name {
+                const SIZE: usize = 1;
+                const SHAPE: &'static layout::Shape =
+                    &layout::Shape::Value(layout::ValueShape::of::<#name>(#description));
+
+                type View<'a> = layout::Value<'a, #name>;
+                type ViewMut<'a> = layout::ValueMut<'a, #name>;
+
+                fn view(bytes: &[u8]) -> Self::View<'_> {
+                    layout::Value::new(bytes)
+                }
+
+                fn view_mut(bytes: &mut [u8]) -> Self::ViewMut<'_> {
+                    layout::ValueMut::new(bytes)
+                }
+
+                fn read(reader: &mut impl ::std::io::Read) -> ::eyre::Result<Self> {
+                    let value = <u8 as layout::Layout>::read(reader)?;
+                    Ok(match value {
+                        #(#known_arms)*
+                        #fallback_arm
+                    })
+                }
+
+                fn write(&self, writer: &mut impl ::std::io::Write) -> ::eyre::Result<()> {
+                    let value: u8 = match self {
+                        #(#write_arms)*
+                    };
+                    layout::Layout::write(&value, writer)
+                }
+            }
+        };
+    })
+}
+
+/// Whether a variant is marked `#[layout(other)]`.
+fn is_other(variant: &syn::Variant) -> syn::Result<bool> {
+    let mut other = false;
+    for attr in &variant.attrs {
+        if !attr.path().is_ident("layout") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("other") {
+                other = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown layout attribute"))
+            }
+        })?;
+    }
+    Ok(other)
+}
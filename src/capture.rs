@@ -0,0 +1,531 @@
+//! Decoding of configuration reports from USB captures, such as those taken
+//! with usbmon or USBPcap while the official app talks to a controller.
+
+pub mod pcap;
+pub mod usb;
+
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+use eyre::WrapErr;
+use tracing::warn;
+
+use crate::driver::ProfileId;
+use crate::driver::protocol::{DEVICE_PREFIX, Direction, HOST_PREFIX, REPORT_SIZE};
+
+/// A configuration report found in a capture.
+#[derive(Clone, Debug)]
+pub struct Report {
+    /// Time since the first packet of the capture.
+    pub time: Duration,
+    pub bus: u16,
+    pub device: u16,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+/// Reads the configuration reports in a pcap or pcapng file, in capture
+/// order.
+///
+/// Reports are recognised by their size and first byte, so traffic from
+/// other devices is skipped unless it happens to look the same.
+pub fn read_reports(path: &Path) -> eyre::Result<Vec<Report>> {
+    let bytes = fs::read(path).wrap_err_with(|| format!("failed to read {}", path.display()))?;
+    reports(&bytes).wrap_err_with(|| format!("invalid capture {}", path.display()))
+}
+
+/// Finds the configuration reports in the bytes of a capture. Packets whose
+/// USB header can't be decoded are skipped with a warning, as captures often
+/// hold traffic from other devices or cut-off packets.
+fn reports(bytes: &[u8]) -> eyre::Result<Vec<Report>> {
+    let packets = pcap::read(bytes)?;
+    let start = packets.first().map(|p| p.timestamp).unwrap_or_default();
+
+    let mut reports = Vec::new();
+    for (i, packet) in packets.iter().enumerate() {
+        let transfer = match usb::decode(packet) {
+            Ok(Some(transfer)) => transfer,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("Skipping packet {}: {e:#}", i + 1);
+                continue;
+            }
+        };
+        if transfer.data.len() != REPORT_SIZE {
+            continue;
+        }
+        let direction = match (transfer.inbound, transfer.data[0]) {
+            (false, HOST_PREFIX) => Direction::HostToDevice,
+            (true, DEVICE_PREFIX) => Direction::DeviceToHost,
+            _ => continue,
+        };
+        reports.push(Report {
+            time: packet.timestamp.saturating_sub(start),
+            bus: transfer.bus,
+            device: transfer.device,
+            direction,
+            data: transfer.data,
+        });
+    }
+
+    Ok(reports)
+}
+
+/// A profile transfer rebuilt from the chunks that carried it.
+#[derive(Clone, Debug)]
+pub enum ProfileTransfer {
+    /// A whole profile was read.
+    Read { profile: ProfileId, bytes: Vec<u8> },
+    /// A contiguous range of a profile was written.
+    Write {
+        profile: ProfileId,
+        range: Range<usize>,
+        bytes: Vec<u8>,
+        /// The whole profile before and after the write, if it was read
+        /// earlier in the capture.
+        before: Option<Vec<u8>>,
+        after: Option<Vec<u8>>,
+    },
+}
+
+struct PendingWrite {
+    profile: ProfileId,
+    start: usize,
+    bytes: Vec<u8>,
+}
+
+/// Rebuilds profile reads and writes from a sequence of reports, keeping
+/// track of what each profile holds as far as the capture shows.
+#[derive(Default)]
+pub struct ProfileTracker {
+    /// Profiles known in full.
+    known: HashMap<ProfileId, Vec<u8>>,
+    /// Reads in progress, with the bytes received so far.
+    reading: HashMap<ProfileId, Vec<u8>>,
+    writing: Option<PendingWrite>,
+}
+
+impl ProfileTracker {
+    pub fn new() -> ProfileTracker {
+        ProfileTracker::default()
+    }
+
+    /// Feeds the next report, returning the transfers it completes.
+    pub fn push(&mut self, report: &[u8]) -> Vec<ProfileTransfer> {
+        let mut done = Vec::new();
+        let Some(&[prefix, id, index, offset_hi, offset_lo, length]) = report.get(..6) else {
+            return done;
+        };
+        let offset = u16::from_be_bytes([offset_hi, offset_lo]) as usize;
+        let length = (length as usize).min(REPORT_SIZE - 6);
+        let chunk = &report[6..(6 + length).min(report.len())];
+        let profile = ProfileId::from_index(index);
+
+        match (prefix, id) {
+            (HOST_PREFIX, 0x03) => {
+                let Some(profile) = profile else {
+                    return done;
+                };
+                match &mut self.writing {
+                    Some(w) if w.profile == profile && w.start + w.bytes.len() == offset => {
+                        w.bytes.extend_from_slice(chunk);
+                    }
+                    _ => {
+                        done.extend(self.finish_write());
+                        self.writing = Some(PendingWrite {
+                            profile,
+                            start: offset,
+                            bytes: chunk.to_vec(),
+                        });
+                    }
+                }
+            }
+            // Acks and replies can come between the chunks of a write, but
+            // any other command ends it
+            (HOST_PREFIX, _) => done.extend(self.finish_write()),
+            (DEVICE_PREFIX, 0x05) => {
+                let Some(profile) = profile else {
+                    return done;
+                };
+                let size = profile.kind().size();
+                if offset == 0 {
+                    self.reading.insert(profile, Vec::with_capacity(size));
+                }
+                let Some(bytes) = self.reading.get_mut(&profile) else {
+                    return done;
+                };
+                if bytes.len() != offset || offset + chunk.len() > size {
+                    // A partial read, or one we joined halfway through
+                    self.reading.remove(&profile);
+                    return done;
+                }
+                bytes.extend_from_slice(chunk);
+                if bytes.len() == size {
+                    let bytes = self.reading.remove(&profile).unwrap();
+                    self.known.insert(profile, bytes.clone());
+                    done.push(ProfileTransfer::Read { profile, bytes });
+                }
+            }
+            _ => {}
+        }
+
+        done
+    }
+
+    /// Ends the capture, returning any transfer still in progress.
+    pub fn finish(&mut self) -> Vec<ProfileTransfer> {
+        self.reading.clear();
+        self.finish_write().into_iter().collect()
+    }
+
+    fn finish_write(&mut self) -> Option<ProfileTransfer> {
+        let PendingWrite {
+            profile,
+            start,
+            bytes,
+        } = self.writing.take()?;
+        let range = start..start + bytes.len();
+
+        let before = self.known.get(&profile).cloned();
+        let after = before.as_ref().and_then(|before| {
+            let mut after = before.clone();
+            after.get_mut(range.clone())?.copy_from_slice(&bytes);
+            Some(after)
+        });
+        match &after {
+            Some(after) => {
+                self.known.insert(profile, after.clone());
+            }
+            None => {
+                self.known.remove(&profile);
+            }
+        }
+
+        Some(ProfileTransfer::Write {
+            profile,
+            range,
+            bytes,
+            before,
+            after,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::ProfileNum;
+
+    /// A configuration report with the given prefix and command.
+    fn report(prefix: u8, id: u8) -> Vec<u8> {
+        let mut report = vec![0; REPORT_SIZE];
+        report[..2].copy_from_slice(&[prefix, id]);
+        report
+    }
+
+    /// A usbmon packet, with a header of `header_len` bytes in the given
+    /// byte order.
+    fn usbmon(
+        header_len: usize,
+        event: u8,
+        endpoint: u8,
+        data: &[u8],
+        big_endian: bool,
+    ) -> Vec<u8> {
+        let mut packet = vec![0; header_len];
+        packet[8] = event;
+        packet[9] = 1; // interrupt
+        packet[10] = endpoint;
+        packet[11] = 5; // device
+        let bus = if big_endian {
+            3u16.to_be_bytes()
+        } else {
+            3u16.to_le_bytes()
+        };
+        packet[12..14].copy_from_slice(&bus);
+        // flag_data is b'<' when there's no data
+        packet[15] = if data.is_empty() { b'<' } else { 0 };
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    /// A USBPcap packet, with the control stage appended to the header when
+    /// given.
+    fn usbpcap(transfer_type: u8, inbound: bool, stage: Option<u8>, data: &[u8]) -> Vec<u8> {
+        let header_len: u16 = if stage.is_some() { 28 } else { 27 };
+        let mut packet = vec![0; header_len.into()];
+        packet[..2].copy_from_slice(&header_len.to_le_bytes());
+        packet[16] = inbound.into();
+        packet[17..19].copy_from_slice(&2u16.to_le_bytes());
+        packet[19..21].copy_from_slice(&7u16.to_le_bytes());
+        packet[21] = if inbound { 0x81 } else { 0x01 };
+        packet[22] = transfer_type;
+        packet[23..27].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        if let Some(stage) = stage {
+            packet[27] = stage;
+        }
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    /// A pcap file holding `packets`, one a millisecond after another.
+    fn pcap(link_type: u32, big_endian: bool, packets: &[Vec<u8>]) -> Vec<u8> {
+        let u16 = |n: u16| {
+            if big_endian {
+                n.to_be_bytes()
+            } else {
+                n.to_le_bytes()
+            }
+        };
+        let u32 = |n: u32| {
+            if big_endian {
+                n.to_be_bytes()
+            } else {
+                n.to_le_bytes()
+            }
+        };
+        let mut file = Vec::new();
+        file.extend(u32(0xa1b2_c3d4));
+        file.extend(u16(2));
+        file.extend(u16(4));
+        file.extend([0; 8]);
+        file.extend(u32(65535));
+        file.extend(u32(link_type));
+        for (i, packet) in packets.iter().enumerate() {
+            file.extend(u32(100));
+            file.extend(u32(i as u32 * 1000));
+            file.extend(u32(packet.len() as u32));
+            file.extend(u32(packet.len() as u32));
+            file.extend(packet);
+        }
+        file
+    }
+
+    fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let len = (12 + body.len().next_multiple_of(4)) as u32;
+        let mut block = Vec::new();
+        block.extend(block_type.to_le_bytes());
+        block.extend(len.to_le_bytes());
+        block.extend(body);
+        block.resize(len as usize - 4, 0);
+        block.extend(len.to_le_bytes());
+        block
+    }
+
+    /// A little-endian pcapng file with one interface with nanosecond
+    /// timestamps, holding `packets` one a millisecond after another.
+    fn pcapng(link_type: u16, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut section = Vec::new();
+        section.extend(0x1a2b_3c4du32.to_le_bytes());
+        section.extend(1u16.to_le_bytes());
+        section.extend(0u16.to_le_bytes());
+        section.extend((-1i64).to_le_bytes());
+        let mut file = pcapng_block(0x0a0d_0d0a, &section);
+
+        let mut interface = Vec::new();
+        interface.extend(link_type.to_le_bytes());
+        interface.extend([0; 2]);
+        interface.extend(0u32.to_le_bytes());
+        // if_tsresol of 10^-9, then the end of the options
+        interface.extend(9u16.to_le_bytes());
+        interface.extend(1u16.to_le_bytes());
+        interface.extend([9, 0, 0, 0]);
+        interface.extend([0; 4]);
+        file.extend(pcapng_block(1, &interface));
+
+        for (i, packet) in packets.iter().enumerate() {
+            let time = 5_000_000_000 + i as u64 * 1_000_000;
+            let mut body = Vec::new();
+            body.extend(0u32.to_le_bytes());
+            body.extend(((time >> 32) as u32).to_le_bytes());
+            body.extend((time as u32).to_le_bytes());
+            body.extend((packet.len() as u32).to_le_bytes());
+            body.extend((packet.len() as u32).to_le_bytes());
+            body.extend(packet);
+            file.extend(pcapng_block(6, &body));
+        }
+        file
+    }
+
+    #[test]
+    fn pcap_with_usbmon() {
+        let host = report(HOST_PREFIX, 0x0b);
+        let device = report(DEVICE_PREFIX, 0x0c);
+        let packets = [
+            usbmon(48, b'S', 0x01, &host, false),
+            // The same report, as the OUT transfer completes
+            usbmon(48, b'C', 0x01, &host, false),
+            // An IN transfer being submitted, with no data
+            usbmon(48, b'S', 0x81, &[], false),
+            // A cut-off packet
+            vec![0; 20],
+            usbmon(48, b'C', 0x81, &device, false),
+            // A report of another size
+            usbmon(48, b'C', 0x81, &device[..32], false),
+        ];
+        let reports = reports(&pcap(usb::LINKTYPE_USB_LINUX, false, &packets)).unwrap();
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].direction, Direction::HostToDevice);
+        assert_eq!(reports[0].data, host);
+        assert_eq!(reports[0].time, Duration::ZERO);
+        assert_eq!(reports[1].direction, Direction::DeviceToHost);
+        assert_eq!(reports[1].data, device);
+        assert_eq!(reports[1].time, Duration::from_millis(4));
+        assert_eq!((reports[1].bus, reports[1].device), (3, 5));
+    }
+
+    #[test]
+    fn big_endian_pcap_with_mmapped_usbmon() {
+        let host = report(HOST_PREFIX, 0x09);
+        let packets = [usbmon(64, b'S', 0x02, &host, true)];
+        let reports = reports(&pcap(usb::LINKTYPE_USB_LINUX_MMAPPED, true, &packets)).unwrap();
+
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].data, host);
+        assert_eq!((reports[0].bus, reports[0].device), (3, 5));
+    }
+
+    #[test]
+    fn pcapng_with_usbpcap() {
+        let host = report(HOST_PREFIX, 0x04);
+        let device = report(DEVICE_PREFIX, 0x05);
+        let mut bad_header = usbpcap(1, true, None, &device);
+        bad_header[..2].copy_from_slice(&500u16.to_le_bytes());
+        let packets = [
+            // The setup stage of a control transfer carries no report
+            usbpcap(2, false, Some(0), &[0x21, 0x09, 0, 2, 0, 0, 64, 0]),
+            usbpcap(2, false, Some(1), &host),
+            bad_header,
+            usbpcap(1, true, None, &device),
+        ];
+        let reports = reports(&pcapng(usb::LINKTYPE_USBPCAP as u16, &packets)).unwrap();
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].direction, Direction::HostToDevice);
+        assert_eq!(reports[0].data, host);
+        assert_eq!(reports[1].direction, Direction::DeviceToHost);
+        assert_eq!(reports[1].data, device);
+        assert_eq!(reports[1].time, Duration::from_millis(3));
+        assert_eq!((reports[1].bus, reports[1].device), (2, 7));
+    }
+
+    #[test]
+    fn other_link_types_are_skipped() {
+        let packets = [report(HOST_PREFIX, 0x0b)];
+        assert!(reports(&pcap(1, false, &packets)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated_captures_are_rejected() {
+        let packets = [usbmon(48, b'S', 0x01, &report(HOST_PREFIX, 0x0b), false)];
+        let file = pcap(usb::LINKTYPE_USB_LINUX, false, &packets);
+        assert!(reports(&file[..file.len() - 1]).is_err());
+        let file = pcapng(usb::LINKTYPE_USB_LINUX as u16, &packets);
+        assert!(reports(&file[..file.len() - 4]).is_err());
+        assert!(reports(b"not a capture").is_err());
+    }
+
+    /// The chunks of a transfer of `bytes` at `offset`, as sent with the
+    /// given prefix and command.
+    fn chunks(prefix: u8, id: u8, profile: ProfileId, offset: usize, bytes: &[u8]) -> Vec<Vec<u8>> {
+        bytes
+            .chunks(REPORT_SIZE - 6)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let offset = (offset + i * (REPORT_SIZE - 6)) as u16;
+                let mut report = report(prefix, id);
+                report[2] = profile.index();
+                report[3..5].copy_from_slice(&offset.to_be_bytes());
+                report[5] = chunk.len() as u8;
+                report[6..6 + chunk.len()].copy_from_slice(chunk);
+                report
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tracker_rebuilds_reads_and_writes() {
+        let profile = ProfileId::Num(ProfileNum::P2);
+        let size = profile.kind().size();
+        let before: Vec<u8> = (0..size).map(|i| i as u8).collect();
+        let mut tracker = ProfileTracker::new();
+
+        let mut done = Vec::new();
+        for chunk in chunks(DEVICE_PREFIX, 0x05, profile, 0, &before) {
+            done.extend(tracker.push(&chunk));
+        }
+        let [
+            ProfileTransfer::Read {
+                profile: read,
+                bytes,
+            },
+        ] = done.as_slice()
+        else {
+            panic!("{done:?}");
+        };
+        assert_eq!((*read, bytes), (profile, &before));
+
+        // A write of two chunks, with an ack after each
+        let written = vec![0xee; 70];
+        let mut done = Vec::new();
+        for chunk in chunks(HOST_PREFIX, 0x03, profile, 100, &written) {
+            done.extend(tracker.push(&chunk));
+            done.extend(tracker.push(&report(DEVICE_PREFIX, 0x06)));
+        }
+        assert!(done.is_empty());
+        done.extend(tracker.push(&report(HOST_PREFIX, 0x08)));
+
+        let [
+            ProfileTransfer::Write {
+                profile: write,
+                range,
+                bytes,
+                before: Some(old),
+                after: Some(new),
+            },
+        ] = done.as_slice()
+        else {
+            panic!("{done:?}");
+        };
+        assert_eq!(*write, profile);
+        assert_eq!(*range, 100..170);
+        assert_eq!(bytes, &written);
+        assert_eq!(old, &before);
+        let mut after = before.clone();
+        after[100..170].copy_from_slice(&written);
+        assert_eq!(new, &after);
+    }
+
+    #[test]
+    fn tracker_ignores_reads_joined_halfway() {
+        let profile = ProfileId::Num(ProfileNum::P1);
+        let bytes = vec![1; profile.kind().size()];
+        let mut tracker = ProfileTracker::new();
+        for chunk in chunks(DEVICE_PREFIX, 0x05, profile, 0, &bytes)
+            .iter()
+            .skip(1)
+        {
+            assert!(tracker.push(chunk).is_empty());
+        }
+
+        // A write to a profile that wasn't read has no before or after
+        let chunk = &chunks(HOST_PREFIX, 0x03, profile, 0, &[2])[0];
+        assert!(tracker.push(chunk).is_empty());
+        let done = tracker.finish();
+        assert!(
+            matches!(
+                done.as_slice(),
+                [ProfileTransfer::Write {
+                    before: None,
+                    after: None,
+                    ..
+                }]
+            ),
+            "{done:?}"
+        );
+    }
+}
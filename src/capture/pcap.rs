@@ -0,0 +1,246 @@
+//! Readers for the pcap and pcapng capture file formats.
+
+use std::time::Duration;
+
+use eyre::{bail, ensure, eyre};
+
+/// A captured packet.
+#[derive(Clone, Debug)]
+pub struct Packet {
+    pub link_type: u32,
+    /// Capture time, since the Unix epoch.
+    pub timestamp: Duration,
+    /// Whether the capture was written big-endian, which also applies to
+    /// headers written by the capturing host, such as usbmon's.
+    pub big_endian: bool,
+    pub data: Vec<u8>,
+}
+
+/// Reads every packet in a pcap or pcapng file.
+pub fn read(bytes: &[u8]) -> eyre::Result<Vec<Packet>> {
+    ensure!(bytes.len() >= 4, "file is too short to be a capture");
+    match u32::from_le_bytes(bytes[..4].try_into().unwrap()) {
+        0xa1b2_c3d4 | 0xd4c3_b2a1 | 0xa1b2_3c4d | 0x4d3c_b2a1 => read_pcap(bytes),
+        0x0a0d_0d0a => read_pcapng(bytes),
+        _ => bail!("not a pcap or pcapng file"),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], big_endian: bool) -> Reader<'a> {
+        Reader {
+            bytes,
+            pos: 0,
+            big_endian,
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize) -> eyre::Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| eyre!("capture is truncated at offset {}", self.pos))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> eyre::Result<u16> {
+        let bytes = self.take(2)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&mut self) -> eyre::Result<u32> {
+        let bytes = self.take(4)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+fn read_pcap(bytes: &[u8]) -> eyre::Result<Vec<Packet>> {
+    let magic = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+    let big_endian = matches!(magic, 0xd4c3_b2a1 | 0x4d3c_b2a1);
+    let nanos = matches!(magic, 0xa1b2_3c4d | 0x4d3c_b2a1);
+
+    let mut reader = Reader::new(bytes, big_endian);
+    // magic, version, thiszone, sigfigs, snaplen
+    reader.take(20)?;
+    let link_type = reader.u32()? & 0x0fff_ffff;
+
+    let mut packets = Vec::new();
+    while reader.remaining() > 0 {
+        let secs = reader.u32()?;
+        let frac = reader.u32()?;
+        let captured_len = reader.u32()?;
+        let _original_len = reader.u32()?;
+        let data = reader.take(captured_len as usize)?;
+
+        let frac = if nanos {
+            Duration::from_nanos(frac.into())
+        } else {
+            Duration::from_micros(frac.into())
+        };
+        packets.push(Packet {
+            link_type,
+            timestamp: Duration::from_secs(secs.into()) + frac,
+            big_endian,
+            data: data.to_vec(),
+        });
+    }
+
+    Ok(packets)
+}
+
+const SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION: u32 = 1;
+const OBSOLETE_PACKET: u32 = 2;
+const SIMPLE_PACKET: u32 = 3;
+const ENHANCED_PACKET: u32 = 6;
+
+struct Interface {
+    link_type: u32,
+    snap_len: u32,
+    /// Length of a timestamp unit.
+    resolution: f64,
+}
+
+fn read_pcapng(bytes: &[u8]) -> eyre::Result<Vec<Packet>> {
+    let mut packets = Vec::new();
+    let mut interfaces = Vec::new();
+    let mut big_endian = false;
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        ensure!(
+            bytes.len() - pos >= 12,
+            "capture is truncated at offset {pos}"
+        );
+        let block_type = u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap());
+
+        if block_type == SECTION_HEADER {
+            // The byte order magic sets the endianness for the whole section
+            let magic = &bytes[pos + 8..pos + 12];
+            big_endian = match magic {
+                [0x1a, 0x2b, 0x3c, 0x4d] => true,
+                [0x4d, 0x3c, 0x2b, 0x1a] => false,
+                _ => bail!("invalid pcapng byte order magic at offset {pos}"),
+            };
+            interfaces.clear();
+        }
+
+        let mut header = Reader::new(&bytes[pos..], big_endian);
+        let block_type = header.u32()?;
+        let total_len = header.u32()? as usize;
+        ensure!(
+            total_len >= 12 && total_len.is_multiple_of(4) && pos + total_len <= bytes.len(),
+            "invalid pcapng block length {total_len} at offset {pos}"
+        );
+        let mut body = Reader::new(&bytes[pos + 8..pos + total_len - 4], big_endian);
+
+        match block_type {
+            INTERFACE_DESCRIPTION => {
+                let link_type = body.u16()?.into();
+                body.take(2)?;
+                let snap_len = body.u32()?;
+                let resolution = read_ts_resolution(&mut body)?;
+                interfaces.push(Interface {
+                    link_type,
+                    snap_len,
+                    resolution,
+                });
+            }
+            ENHANCED_PACKET | OBSOLETE_PACKET => {
+                let interface = if block_type == ENHANCED_PACKET {
+                    body.u32()? as usize
+                } else {
+                    let interface = body.u16()? as usize;
+                    body.take(2)?;
+                    interface
+                };
+                let high = body.u32()? as u64;
+                let low = body.u32()? as u64;
+                let captured_len = body.u32()? as usize;
+                let _original_len = body.u32()?;
+                let data = body.take(captured_len)?;
+
+                let interface = interfaces
+                    .get(interface)
+                    .ok_or_else(|| eyre!("packet at offset {pos} has no interface"))?;
+                let units = ((high << 32) | low) as f64;
+                let timestamp = Duration::try_from_secs_f64(units * interface.resolution)
+                    .map_err(|_| eyre!("invalid timestamp at offset {pos}"))?;
+                packets.push(Packet {
+                    link_type: interface.link_type,
+                    timestamp,
+                    big_endian,
+                    data: data.to_vec(),
+                });
+            }
+            SIMPLE_PACKET => {
+                let interface = interfaces
+                    .first()
+                    .ok_or_else(|| eyre!("packet at offset {pos} has no interface"))?;
+                let original_len = body.u32()? as usize;
+                let captured_len = match interface.snap_len {
+                    0 => original_len,
+                    snap_len => original_len.min(snap_len as usize),
+                };
+                let data = body.take(captured_len)?;
+                packets.push(Packet {
+                    link_type: interface.link_type,
+                    // Simple packets have no timestamp
+                    timestamp: Duration::ZERO,
+                    big_endian,
+                    data: data.to_vec(),
+                });
+            }
+            _ => {}
+        }
+
+        pos += total_len;
+    }
+
+    Ok(packets)
+}
+
+/// Reads the `if_tsresol` option of an interface description block,
+/// defaulting to microseconds.
+fn read_ts_resolution(options: &mut Reader) -> eyre::Result<f64> {
+    const END_OF_OPTIONS: u16 = 0;
+    const IF_TSRESOL: u16 = 9;
+
+    let mut resolution = 1e-6;
+    while options.remaining() >= 4 {
+        let code = options.u16()?;
+        let len = options.u16()? as usize;
+        if code == END_OF_OPTIONS {
+            break;
+        }
+        let value = options.take(len.next_multiple_of(4))?;
+        if code == IF_TSRESOL && len == 1 {
+            let exponent = (value[0] & 0x7f) as i32;
+            resolution = if value[0] & 0x80 == 0 {
+                10f64.powi(-exponent)
+            } else {
+                2f64.powi(-exponent)
+            };
+        }
+    }
+    Ok(resolution)
+}
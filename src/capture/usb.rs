@@ -0,0 +1,144 @@
+//! Decoding of the USB pseudo-headers that capture tools put in front of
+//! each transfer.
+
+use eyre::{bail, ensure};
+
+use crate::capture::pcap::Packet;
+
+/// Linux usbmon, with the 48-byte header.
+pub const LINKTYPE_USB_LINUX: u32 = 189;
+/// Linux usbmon, with the 64-byte header of the memory-mapped interface.
+pub const LINKTYPE_USB_LINUX_MMAPPED: u32 = 220;
+/// USBPcap, on Windows.
+pub const LINKTYPE_USBPCAP: u32 = 249;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferType {
+    Isochronous,
+    Interrupt,
+    Control,
+    Bulk,
+}
+
+impl TransferType {
+    fn from_code(code: u8) -> Option<TransferType> {
+        match code {
+            0 => Some(TransferType::Isochronous),
+            1 => Some(TransferType::Interrupt),
+            2 => Some(TransferType::Control),
+            3 => Some(TransferType::Bulk),
+            _ => None,
+        }
+    }
+}
+
+/// Data carried by one captured USB transfer.
+#[derive(Clone, Debug)]
+pub struct Transfer {
+    pub bus: u16,
+    pub device: u16,
+    pub endpoint: u8,
+    pub transfer_type: TransferType,
+    /// Whether the data went from the device to the host.
+    pub inbound: bool,
+    pub data: Vec<u8>,
+}
+
+/// Decodes a packet's USB header, returning `None` if it carries no data,
+/// such as the submission of an IN transfer.
+pub fn decode(packet: &Packet) -> eyre::Result<Option<Transfer>> {
+    match packet.link_type {
+        LINKTYPE_USB_LINUX => decode_usbmon(packet, 48),
+        LINKTYPE_USB_LINUX_MMAPPED => decode_usbmon(packet, 64),
+        LINKTYPE_USBPCAP => decode_usbpcap(&packet.data),
+        other => bail!("unsupported link type {other}, expected a USB capture"),
+    }
+}
+
+fn decode_usbmon(packet: &Packet, header_len: usize) -> eyre::Result<Option<Transfer>> {
+    let data = &packet.data;
+    ensure!(data.len() >= header_len, "truncated usbmon header");
+
+    // The header is in the byte order of the capturing host, which is the
+    // byte order of the file
+    let u16_at = |i: usize| {
+        let bytes = [data[i], data[i + 1]];
+        if packet.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    };
+
+    let event = data[8];
+    let Some(transfer_type) = TransferType::from_code(data[9]) else {
+        return Ok(None);
+    };
+    let endpoint = data[10];
+    // flag_data is 0 when the data is present
+    let has_data = data[15] == 0;
+    let payload = &data[header_len..];
+    if !has_data || payload.is_empty() {
+        return Ok(None);
+    }
+
+    // The endpoint address gives the direction. Data is captured on
+    // submission for OUT transfers and on completion for IN transfers, so
+    // anything else would be a copy.
+    let inbound = endpoint & 0x80 != 0;
+    if event != if inbound { b'C' } else { b'S' } {
+        return Ok(None);
+    }
+
+    Ok(Some(Transfer {
+        bus: u16_at(12),
+        device: data[11].into(),
+        endpoint,
+        transfer_type,
+        inbound,
+        data: payload.to_vec(),
+    }))
+}
+
+fn decode_usbpcap(data: &[u8]) -> eyre::Result<Option<Transfer>> {
+    const INFO_PDO_TO_FDO: u8 = 0x01;
+    const CONTROL_STAGE_DATA: u8 = 1;
+
+    ensure!(data.len() >= 27, "truncated USBPcap header");
+    let header_len = u16::from_le_bytes([data[0], data[1]]) as usize;
+    ensure!(
+        (27..=data.len()).contains(&header_len),
+        "invalid USBPcap header length {header_len}"
+    );
+
+    let info = data[16];
+    let bus = u16::from_le_bytes([data[17], data[18]]);
+    let device = u16::from_le_bytes([data[19], data[20]]);
+    let endpoint = data[21];
+    let Some(transfer_type) = TransferType::from_code(data[22]) else {
+        return Ok(None);
+    };
+    // Control transfers are captured one stage at a time, and only the data
+    // stage carries a report
+    if transfer_type == TransferType::Control
+        && data
+            .get(27)
+            .is_some_and(|&stage| stage != CONTROL_STAGE_DATA)
+    {
+        return Ok(None);
+    }
+
+    let payload = &data[header_len..];
+    if payload.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(Transfer {
+        bus,
+        device,
+        endpoint,
+        transfer_type,
+        inbound: info & INFO_PDO_TO_FDO != 0,
+        data: payload.to_vec(),
+    }))
+}
@@ -0,0 +1,167 @@
+pub mod capture;
+pub mod diff;
+pub mod dump;
+pub mod library;
+pub mod plan;
+pub mod raw;
+
+use std::cell::OnceCell;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use eyre::{WrapErr, bail};
+use opengamesir::driver::dry_run::DryRun;
+use opengamesir::driver::protocol;
+use opengamesir::driver::trace::{Recording, Replay};
+use opengamesir::driver::transport::Transport;
+use opengamesir::driver::{ControlProfile, Cyclone2, Layout, LightProfile, ProfileId, ProfileKind};
+use opengamesir::hid::Hid;
+use opengamesir::snapshot::SnapshotStore;
+
+/// Connects to the controller the first time it is needed, so that commands
+/// working only on files don't require one, nor access to HID devices or the
+/// snapshot directory.
+pub struct Connection {
+    // Dropped before `hid`, which it may use
+    device: OnceCell<Cyclone2>,
+    hid: OnceCell<Hid>,
+    snapshots: bool,
+    dry_run: bool,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+}
+
+impl Connection {
+    pub fn new() -> Connection {
+        Connection {
+            device: OnceCell::new(),
+            hid: OnceCell::new(),
+            snapshots: false,
+            dry_run: false,
+            record: None,
+            replay: None,
+        }
+    }
+
+    /// Writes every report exchanged with the controller to a trace file.
+    pub fn record_to(&mut self, path: PathBuf) {
+        self.record = Some(path);
+    }
+
+    /// Serves the reports in a trace file instead of using a controller.
+    pub fn replay_from(&mut self, path: PathBuf) {
+        self.replay = Some(path);
+    }
+
+    /// Prints commands that would change the controller instead of sending
+    /// them.
+    pub fn dry_run(&mut self) {
+        self.dry_run = true;
+    }
+
+    /// Records a snapshot of each profile in the default store before it is
+    /// written.
+    pub fn record_snapshots(&mut self) {
+        self.snapshots = true;
+    }
+
+    pub fn get(&self) -> eyre::Result<&Cyclone2> {
+        if let Some(device) = self.device.get() {
+            return Ok(device);
+        }
+        let (mut transport, variant) = match &self.replay {
+            Some(path) => {
+                let replay = Replay::open(path)?;
+                let variant = replay.variant();
+                (Box::new(replay) as Box<dyn Transport>, variant)
+            }
+            None => {
+                let hid = match self.hid.get() {
+                    Some(hid) => hid,
+                    None => {
+                        let hid = Hid::new()?;
+                        self.hid.get_or_init(|| hid)
+                    }
+                };
+                Cyclone2::open_transport(hid)?
+            }
+        };
+        if let Some(path) = &self.record {
+            transport = Box::new(Recording::create(transport, variant, path)?);
+        }
+        let transport = if self.dry_run {
+            Box::new(DryRun::new(transport, |report| {
+                print_report("dry run:", report)
+            }))
+        } else {
+            transport
+        };
+        let mut device = Cyclone2::with_transport(transport, variant);
+        // Nothing is overwritten in a dry run, and a replayed controller's
+        // profiles aren't the user's
+        if self.snapshots && !self.dry_run && self.replay.is_none() {
+            let store = SnapshotStore::open_default()?;
+            device.set_write_hook(move |id, bytes| {
+                store.record(id, bytes)?;
+                Ok(())
+            });
+        }
+        Ok(self.device.get_or_init(|| device))
+    }
+}
+
+/// Prints a decoded report followed by its bytes.
+pub fn print_report(marker: &str, report: &[u8]) {
+    println!("{marker} {}", protocol::annotate(report));
+    for line in report.chunks(16) {
+        let hex: Vec<_> = line.iter().map(|b| format!("{b:02x}")).collect();
+        println!("  {}", hex.join(" "));
+    }
+}
+
+/// Where to read a profile from: a slot on the device, or a raw dump file.
+#[derive(Clone, Debug)]
+pub enum ProfileSource {
+    Device(ProfileId),
+    File(PathBuf),
+}
+
+impl FromStr for ProfileSource {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<ProfileSource> {
+        Ok(match s.parse() {
+            Ok(id) => ProfileSource::Device(id),
+            Err(_) => ProfileSource::File(s.into()),
+        })
+    }
+}
+
+impl ProfileSource {
+    /// Reads the profile's bytes, returning them with the kind of profile
+    /// they hold. Raw files are told apart by their size.
+    pub fn load(&self, connection: &Connection) -> eyre::Result<(ProfileKind, Vec<u8>)> {
+        match self {
+            ProfileSource::Device(id) => {
+                let c2 = connection.get()?;
+                let kind = id.kind();
+                Ok((kind, c2.read_profile(*id, kind.size())?))
+            }
+            ProfileSource::File(path) => {
+                let bytes = fs::read(path)
+                    .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+                let Some(kind) = ProfileKind::from_size(bytes.len()) else {
+                    bail!(
+                        "{} is {} bytes, expected {} for a control profile or {} for a light profile",
+                        path.display(),
+                        bytes.len(),
+                        ControlProfile::SIZE,
+                        LightProfile::SIZE
+                    );
+                };
+                Ok((kind, bytes))
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::path::Path;
+
+use eyre::bail;
+use opengamesir::capture::{self, ProfileTracker, ProfileTransfer};
+use opengamesir::driver::protocol::{self, Direction};
+use opengamesir::driver::registry;
+
+use crate::cli::{diff, print_report};
+
+pub struct DecodeOptions {
+    /// Only decode reports of the device at this bus and address.
+    pub device: Option<(u16, u16)>,
+    /// Print each report's bytes under its description.
+    pub hex: bool,
+}
+
+/// Parses a device given as `<bus>.<address>`, as shown by `lsusb` and in
+/// the decoded output.
+pub fn parse_device(s: &str) -> eyre::Result<(u16, u16)> {
+    let Some((bus, address)) = s.split_once('.') else {
+        bail!("expected a device of the form <bus>.<address>");
+    };
+    Ok((bus.parse()?, address.parse()?))
+}
+
+/// Prints the configuration reports in a capture, followed by the profile
+/// reads and writes they make up.
+pub fn decode(path: &Path, options: &DecodeOptions) -> eyre::Result<()> {
+    let reports = capture::read_reports(path)?;
+    let reports: Vec<_> = reports
+        .into_iter()
+        .filter(|r| options.device.is_none_or(|d| d == (r.bus, r.device)))
+        .collect();
+    if reports.is_empty() {
+        bail!("no configuration reports found in {}", path.display());
+    }
+
+    let mut tracker = ProfileTracker::new();
+    for report in &reports {
+        let marker = format!(
+            "{:>11.6} {}.{} {}",
+            report.time.as_secs_f64(),
+            report.bus,
+            report.device,
+            match report.direction {
+                Direction::HostToDevice => ">",
+                Direction::DeviceToHost => "<",
+            }
+        );
+        if options.hex {
+            print_report(&marker, &report.data);
+        } else {
+            println!("{marker} {}", protocol::annotate(&report.data));
+        }
+
+        for transfer in tracker.push(&report.data) {
+            print_transfer(&transfer);
+        }
+    }
+    for transfer in tracker.finish() {
+        print_transfer(&transfer);
+    }
+
+    Ok(())
+}
+
+fn print_transfer(transfer: &ProfileTransfer) {
+    match transfer {
+        ProfileTransfer::Read { profile, bytes } => {
+            println!("== read profile {profile} ({} bytes)", bytes.len());
+        }
+        ProfileTransfer::Write {
+            profile,
+            range,
+            bytes,
+            before,
+            after,
+        } => {
+            println!(
+                "== wrote profile {profile}, bytes {:#06x}..{:#06x}",
+                range.start, range.end
+            );
+            let kind = profile.kind();
+
+            if let (Some(before), Some(after)) = (before, after) {
+                let changes = registry::diff(kind.shape(), kind.size(), before, after);
+                if changes.is_empty() {
+                    println!("   no changes");
+                }
+                for change in changes {
+                    println!("   {}", diff::describe(&change));
+                }
+                return;
+            }
+
+            // Without an earlier read, only the written values are known
+            let mut image = vec![0; kind.size()];
+            let Some(written) = image.get_mut(range.clone()) else {
+                println!("   past the end of a {kind} profile");
+                return;
+            };
+            written.copy_from_slice(bytes);
+            for leaf in registry::root(kind.shape(), kind.size()).leaves() {
+                let end = leaf.offset + leaf.size;
+                if end <= range.start || leaf.offset >= range.end {
+                    continue;
+                }
+                if leaf.offset >= range.start && end <= range.end {
+                    let value = leaf
+                        .format(&image)
+                        .unwrap_or_else(|e| format!("invalid ({e})"));
+                    println!("   {} = {value}", leaf.path);
+                } else {
+                    println!("   {} (partly written)", leaf.path);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use eyre::ensure;
+use opengamesir::driver::ProfileKind;
+use opengamesir::driver::layout::FieldKind;
+use opengamesir::driver::registry::{self, Change};
+
+/// Prints the fields that differ between two profiles, one per line.
+pub fn print(
+    (old_kind, old): (ProfileKind, Vec<u8>),
+    (new_kind, new): (ProfileKind, Vec<u8>),
+) -> eyre::Result<()> {
+    ensure!(
+        old_kind == new_kind,
+        "cannot compare a {} profile with a {} profile",
+        old_kind,
+        new_kind
+    );
+
+    for change in registry::diff(old_kind.shape(), old_kind.size(), &old, &new) {
+        println!("{}", describe(&change));
+    }
+
+    Ok(())
+}
+
+/// Describes a change in one line, e.g. `left_stick.front_dead: 0 → 5`.
+pub fn describe(change: &Change) -> String {
+    let note = match (change.path.as_str(), change.kind) {
+        ("<reserved>", _) => format!(" (offset {:#06x})", change.offset),
+        (_, FieldKind::Known) => String::new(),
+        (_, FieldKind::Reserved) => " (reserved)".to_owned(),
+        (_, FieldKind::Unknown) => " (unknown)".to_owned(),
+    };
+    format!("{}: {} → {}{note}", change.path, change.old, change.new)
+}
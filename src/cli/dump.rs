@@ -0,0 +1,112 @@
+use std::io::IsTerminal;
+
+use opengamesir::driver::ProfileKind;
+use opengamesir::driver::layout::FieldKind;
+use opengamesir::driver::registry;
+
+const BYTES_PER_LINE: usize = 8;
+
+const RESERVED_COLOR: &str = "\x1b[33m";
+const UNKNOWN_COLOR: &str = "\x1b[35m";
+const ERROR_COLOR: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+struct Row {
+    offset: usize,
+    bytes: Vec<u8>,
+    name: String,
+    value: String,
+    color: Option<&'static str>,
+}
+
+/// Prints an annotated hex dump of a profile's bytes, one row per field.
+///
+/// Bytes that are not covered by any field are shown as reserved.
+pub fn print(kind: ProfileKind, bytes: &[u8]) {
+    let mut rows = Vec::new();
+    let mut pos = 0;
+
+    for leaf in registry::root(kind.shape(), kind.size()).leaves() {
+        if leaf.offset > pos {
+            rows.push(reserved_row(pos, &bytes[pos..leaf.offset]));
+        }
+
+        let (mut value, mut color) = match leaf.format(bytes) {
+            Ok(value) => (value, None),
+            Err(e) => (format!("invalid: {e}"), Some(ERROR_COLOR)),
+        };
+
+        match leaf.kind {
+            FieldKind::Known => {}
+            FieldKind::Reserved => {
+                value = format!("{value} (reserved)");
+                color = color.or(Some(RESERVED_COLOR));
+            }
+            FieldKind::Unknown => {
+                value = format!("{value} (unknown)");
+                color = color.or(Some(UNKNOWN_COLOR));
+            }
+        }
+
+        rows.push(Row {
+            offset: leaf.offset,
+            bytes: leaf.bytes(bytes).to_vec(),
+            name: leaf.path,
+            value,
+            color,
+        });
+
+        pos = leaf.offset + leaf.size;
+    }
+
+    if pos < bytes.len() {
+        rows.push(reserved_row(pos, &bytes[pos..]));
+    }
+
+    let use_color = std::io::stdout().is_terminal();
+    let name_width = rows.iter().map(|row| row.name.len()).max().unwrap_or(0);
+    let hex_width = BYTES_PER_LINE * 3 - 1;
+
+    println!(
+        "{:<6}  {:<hex_width$}  {:<name_width$}  value",
+        "offset", "hex", "field"
+    );
+
+    for row in rows {
+        let (start, end) = match (use_color, row.color) {
+            (true, Some(color)) => (color, RESET),
+            _ => ("", ""),
+        };
+
+        for (i, chunk) in row.bytes.chunks(BYTES_PER_LINE).enumerate() {
+            let hex = chunk
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            if i == 0 {
+                println!(
+                    "{start}{:#06x}  {hex:<hex_width$}  {:<name_width$}  {}{end}",
+                    row.offset, row.name, row.value
+                );
+            } else {
+                println!("{start}{:6}  {hex}{end}", "");
+            }
+        }
+    }
+}
+
+fn reserved_row(offset: usize, bytes: &[u8]) -> Row {
+    let all_zero = bytes.iter().all(|&b| b == 0);
+    Row {
+        offset,
+        bytes: bytes.to_vec(),
+        name: "<reserved>".to_owned(),
+        value: if all_zero {
+            format!("{} bytes", bytes.len())
+        } else {
+            format!("{} bytes, not all zero", bytes.len())
+        },
+        color: Some(RESERVED_COLOR),
+    }
+}
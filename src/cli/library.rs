@@ -0,0 +1,94 @@
+use eyre::ensure;
+use opengamesir::driver::{ProfileId, ProfileKind};
+use opengamesir::library::{Entry, Library};
+
+use crate::cli::{Connection, ProfileSource};
+
+#[derive(clap::Subcommand)]
+pub enum LibraryAction {
+    /// Saves a control profile to the library.
+    Save {
+        name: String,
+        /// Profile number (1-4), `shift`, or a raw dump file.
+        source: ProfileSource,
+        /// Tag to attach, can be given more than once.
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        #[arg(long, default_value = "")]
+        notes: String,
+        /// Replace an existing profile with the same name.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Lists saved profiles.
+    List {
+        /// Only list profiles with this tag.
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// Writes a saved profile to a slot on the controller.
+    Apply {
+        name: String,
+        /// Profile number (1-4) or `shift`.
+        slot: ProfileId,
+    },
+    /// Removes a profile from the library.
+    Delete { name: String },
+}
+
+pub fn run(action: LibraryAction, connection: &Connection) -> eyre::Result<()> {
+    let library = Library::open_default()?;
+
+    match action {
+        LibraryAction::Save {
+            name,
+            source,
+            tags,
+            notes,
+            force,
+        } => {
+            let (kind, bytes) = source.load(connection)?;
+            ensure!(
+                kind == ProfileKind::Control,
+                "only control profiles can be saved"
+            );
+            let mut entry = Entry::from_bytes(&name, bytes)?;
+            entry.tags = tags;
+            entry.notes = notes;
+            library.save(&entry, force)?;
+            println!("Saved {name}");
+        }
+        LibraryAction::List { tag } => {
+            for entry in library.list()? {
+                if tag.as_ref().is_some_and(|tag| !entry.has_tag(tag)) {
+                    continue;
+                }
+                let mut line = entry.name.clone();
+                if !entry.tags.is_empty() {
+                    line += &format!(" [{}]", entry.tags.join(", "));
+                }
+                if !entry.notes.is_empty() {
+                    line += &format!(" - {}", entry.notes);
+                }
+                println!("{line}");
+            }
+        }
+        LibraryAction::Apply { name, slot } => {
+            ensure!(
+                slot.kind() == ProfileKind::Control,
+                "library profiles can only be applied to control profile slots"
+            );
+            let entry = library.load(&name)?;
+            let c2 = connection.get()?;
+            let old = c2.read_profile(slot, ProfileKind::Control.size())?;
+            c2.update_profile(slot, &old, entry.bytes())?;
+            println!("Applied {name} to profile {slot}");
+        }
+        LibraryAction::Delete { name } => {
+            library.delete(&name)?;
+            println!("Deleted {name}");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,26 @@
+use opengamesir::plan::Plan;
+
+use crate::cli::diff;
+
+/// Prints the changes a plan would make, grouped by profile.
+pub fn print(plan: &Plan) {
+    for profile in &plan.profiles {
+        let ranges: Vec<_> = profile
+            .ranges()
+            .iter()
+            .map(|range| format!("{:#06x}..{:#06x}", range.start, range.end))
+            .collect();
+        println!(
+            "profile {}: {} changed field(s), writes bytes {}",
+            profile.id,
+            profile.changes.len(),
+            ranges.join(", ")
+        );
+        for change in &profile.changes {
+            println!("  {}", diff::describe(change));
+        }
+    }
+    if let Some((current, desired)) = plan.active {
+        println!("active profile: {current} → {desired}");
+    }
+}
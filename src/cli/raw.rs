@@ -0,0 +1,113 @@
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+use eyre::{bail, ensure};
+use opengamesir::driver::protocol::{self, Direction, HOST_PREFIX, REPORT_SIZE};
+use opengamesir::driver::{Cyclone2, hex};
+
+use crate::cli::print_report;
+
+const HELP: &str = "\
+Enter a report as hex bytes, e.g. `0f 09`, to send it and show the replies.
+  wait <ms>  show reports received within the given time
+  help       show this message
+  quit       exit";
+
+pub struct RawOptions {
+    /// How long to wait for replies after sending a report.
+    pub timeout: Duration,
+    /// Send commands that change the controller, or that aren't known.
+    pub allow_writes: bool,
+}
+
+/// Sends one report and prints everything received until `timeout` passes
+/// without a report arriving.
+pub fn send(c2: &Cyclone2, report: &[u8], options: &RawOptions) -> eyre::Result<()> {
+    check(report, options)?;
+    print_report(">", report);
+    c2.write_raw(report)?;
+    receive(c2, options.timeout)
+}
+
+/// Reads reports to send from stdin, one per line.
+pub fn repl(c2: &Cyclone2, options: &RawOptions) -> eyre::Result<()> {
+    println!("{HELP}");
+    let mut stdin = io::stdin().lock();
+
+    loop {
+        // Show anything that arrived while waiting for input
+        receive(c2, Duration::ZERO)?;
+
+        print!("raw> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let line = line.trim();
+        let res = match line.split_once(' ').unwrap_or((line, "")) {
+            ("", _) => Ok(()),
+            ("quit" | "exit", _) => break,
+            ("help", _) => {
+                println!("{HELP}");
+                Ok(())
+            }
+            ("wait", ms) => match ms.trim().parse() {
+                Ok(ms) => receive(c2, Duration::from_millis(ms)),
+                Err(_) => Err(eyre::eyre!("expected a time in milliseconds")),
+            },
+            _ => parse(line).and_then(|report| send(c2, &report, options)),
+        };
+        if let Err(e) = res {
+            println!("error: {e:#}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses hex bytes, which may be separated by whitespace.
+pub fn parse(s: &str) -> eyre::Result<Vec<u8>> {
+    let digits: String = s.split_whitespace().collect();
+    let report = hex::parse(&digits)?;
+    ensure!(!report.is_empty(), "empty report");
+    ensure!(
+        report.len() <= REPORT_SIZE,
+        "reports are at most {REPORT_SIZE} bytes"
+    );
+    Ok(report)
+}
+
+fn check(report: &[u8], options: &RawOptions) -> eyre::Result<()> {
+    if options.allow_writes {
+        return Ok(());
+    }
+    let known_read = match report {
+        [HOST_PREFIX, id, ..] => protocol::command(*id)
+            .is_some_and(|c| c.direction == Direction::HostToDevice && !c.mutating),
+        _ => false,
+    };
+    if !known_read {
+        bail!(
+            "{} may change the controller, use --allow-writes to send it",
+            protocol::annotate(report)
+        );
+    }
+    Ok(())
+}
+
+fn receive(c2: &Cyclone2, timeout: Duration) -> eyre::Result<()> {
+    while let Some(report) = c2.read_raw(timeout)? {
+        print_report("<", &report);
+    }
+    // Reports sent unprompted, such as ProfileChanged, don't arrive with the
+    // replies. Input reports are left out, as they flood in while the
+    // controller is used.
+    if let Some(notifications) = c2.notifications() {
+        while let Some(report) = notifications.try_recv()? {
+            print_report("<", &report);
+        }
+    }
+    Ok(())
+}
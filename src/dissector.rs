@@ -0,0 +1,296 @@
+//! Generation of a Wireshark dissector for the controller's reports, from
+//! the tables in [`protocol`](crate::driver::protocol).
+//!
+//! The dissector handles configuration reports on the vendor interface
+//! (usage page `0xFFF0`) and the gamepad input report (`0x12`), and labels
+//! each packet the same way [`annotate`](crate::driver::protocol::annotate)
+//! does.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::driver::ProfileId;
+use crate::driver::protocol::{
+    COMMANDS, DEVICE_PREFIX, Direction, HOST_PREFIX, INPUT_FIELDS, INPUT_REPORT_ID,
+    INPUT_REPORT_LEN, ParamKind, REPORT_SIZE,
+};
+
+/// Returns the source of a Lua dissector, to be placed in Wireshark's
+/// plugin directory.
+pub fn lua() -> String {
+    let mut out = String::new();
+    // Writing to a String can't fail
+    write_lua(&mut out).unwrap();
+    out
+}
+
+fn write_lua(out: &mut String) -> std::fmt::Result {
+    writeln!(
+        out,
+        "-- Wireshark dissector for GameSir Cyclone 2 reports.\n\
+         -- Generated by `opengamesir wireshark-dissector`; regenerate it rather\n\
+         -- than editing it, so that it keeps matching the opengamesir decoder.\n"
+    )?;
+    writeln!(
+        out,
+        "local proto = Proto(\"gamesir_c2\", \"GameSir Cyclone 2\")\n"
+    )?;
+
+    // Value names
+    for (table, direction) in [
+        ("host_commands", Direction::HostToDevice),
+        ("device_commands", Direction::DeviceToHost),
+    ] {
+        writeln!(out, "local {table} = {{")?;
+        for info in COMMANDS.iter().filter(|c| c.direction == direction) {
+            writeln!(out, "  [{:#04x}] = {:?},", info.id, info.name)?;
+        }
+        writeln!(out, "}}")?;
+    }
+    writeln!(out, "local profiles = {{")?;
+    for id in ProfileId::ALL {
+        writeln!(out, "  [{}] = {:?},", id.index(), id.to_string())?;
+    }
+    writeln!(out, "}}\n")?;
+
+    // Fields
+    writeln!(out, "local f = {{")?;
+    writeln!(
+        out,
+        "  prefix = ProtoField.uint8(\"gamesir_c2.prefix\", \"Prefix\", base.HEX),"
+    )?;
+    writeln!(
+        out,
+        "  host_command = ProtoField.uint8(\"gamesir_c2.command\", \"Command\", base.HEX, host_commands),"
+    )?;
+    writeln!(
+        out,
+        "  device_command = ProtoField.uint8(\"gamesir_c2.reply\", \"Reply\", base.HEX, device_commands),"
+    )?;
+    let mut params = BTreeMap::new();
+    for param in COMMANDS.iter().flat_map(|c| c.params) {
+        params.entry(param.name).or_insert(param.kind);
+    }
+    for (name, kind) in &params {
+        let abbrev = format!("gamesir_c2.{name}");
+        let field = match kind {
+            ParamKind::U8 => format!("ProtoField.uint8({abbrev:?}, {name:?}, base.DEC)"),
+            ParamKind::Profile => {
+                format!("ProtoField.uint8({abbrev:?}, {name:?}, base.DEC, profiles)")
+            }
+            ParamKind::Offset => format!("ProtoField.uint16({abbrev:?}, {name:?}, base.HEX)"),
+            ParamKind::Magic(_) | ParamKind::Data => {
+                format!("ProtoField.bytes({abbrev:?}, {name:?})")
+            }
+        };
+        writeln!(out, "  {name} = {field},")?;
+    }
+    for input in INPUT_FIELDS {
+        let abbrev = format!("gamesir_c2.input.{}", input.name);
+        let field = match (input.size, input.mask) {
+            (1, Some(mask)) => format!(
+                "ProtoField.uint8({abbrev:?}, {:?}, base.DEC, nil, {mask:#04x})",
+                input.label
+            ),
+            (1, None) => format!("ProtoField.uint8({abbrev:?}, {:?}, base.DEC)", input.label),
+            _ => format!("ProtoField.bytes({abbrev:?}, {:?})", input.label),
+        };
+        writeln!(out, "  input_{} = {field},", input.name)?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out, "local fields = {{}}")?;
+    writeln!(out, "for _, field in pairs(f) do")?;
+    writeln!(out, "  table.insert(fields, field)")?;
+    writeln!(out, "end")?;
+    writeln!(out, "proto.fields = fields\n")?;
+
+    // Parameters of each command, as {field, offset, size, kind, magic}
+    for (table, direction) in [
+        ("host_params", Direction::HostToDevice),
+        ("device_params", Direction::DeviceToHost),
+    ] {
+        writeln!(out, "local {table} = {{")?;
+        for info in COMMANDS.iter().filter(|c| c.direction == direction) {
+            if info.params.is_empty() {
+                continue;
+            }
+            writeln!(out, "  [{:#04x}] = {{", info.id)?;
+            for param in info.params {
+                let (kind, magic) = match param.kind {
+                    ParamKind::U8 => ("u8", String::new()),
+                    ParamKind::Profile => ("profile", String::new()),
+                    ParamKind::Offset => ("offset", String::new()),
+                    ParamKind::Data => ("data", String::new()),
+                    ParamKind::Magic(bytes) => {
+                        let bytes: Vec<_> = bytes.iter().map(|b| format!("{b:#04x}")).collect();
+                        ("magic", format!(", magic = {{ {} }}", bytes.join(", ")))
+                    }
+                };
+                writeln!(
+                    out,
+                    "    {{ name = {:?}, field = f.{}, offset = {}, size = {}, kind = {kind:?}{magic} }},",
+                    param.name,
+                    param.name,
+                    param.offset,
+                    param.kind.size()
+                )?;
+            }
+            writeln!(out, "  }},")?;
+        }
+        writeln!(out, "}}")?;
+    }
+    writeln!(out)?;
+
+    writeln!(out, "local input_fields = {{")?;
+    for input in INPUT_FIELDS {
+        writeln!(
+            out,
+            "  {{ field = f.input_{}, offset = {}, size = {} }},",
+            input.name, input.offset, input.size
+        )?;
+    }
+    writeln!(out, "}}\n")?;
+
+    write!(
+        out,
+        "{}",
+        DISSECT
+            .replace("{HOST_PREFIX}", &format!("{HOST_PREFIX:#04x}"))
+            .replace("{DEVICE_PREFIX}", &format!("{DEVICE_PREFIX:#04x}"))
+            .replace("{INPUT_REPORT_ID}", &format!("{INPUT_REPORT_ID:#04x}"))
+            .replace("{INPUT_REPORT_LEN}", &INPUT_REPORT_LEN.to_string())
+            .replace("{REPORT_SIZE}", &REPORT_SIZE.to_string())
+    )
+}
+
+/// The part of the dissector that doesn't depend on the tables. Placeholders
+/// in braces are replaced by the protocol constants.
+const DISSECT: &str = r#"-- Describes a parameter the same way `opengamesir` does
+local function format_param(param, range)
+  local value = range:uint()
+  if param.kind == "offset" then
+    return string.format("0x%04x", value)
+  elseif param.kind == "profile" and profiles[value] then
+    return profiles[value]
+  end
+  return tostring(value)
+end
+
+local function matches(range, bytes)
+  for i, byte in ipairs(bytes) do
+    if range(i - 1, 1):uint() ~= byte then
+      return false
+    end
+  end
+  return true
+end
+
+local function dissect_config(buffer, pinfo, tree, prefix)
+  local id = buffer(1, 1):uint()
+  local names, params, command_field
+  if prefix == {HOST_PREFIX} then
+    names, params, command_field = host_commands, host_params, f.host_command
+  else
+    names, params, command_field = device_commands, device_params, f.device_command
+  end
+
+  local name = names[id]
+  if not name then
+    local info = string.format("unknown command 0x%02x 0x%02x", prefix, id)
+    pinfo.cols.info = info
+    local subtree = tree:add(proto, buffer(), info)
+    subtree:add(f.prefix, buffer(0, 1))
+    subtree:add(command_field, buffer(1, 1))
+    return
+  end
+
+  local subtree = tree:add(proto, buffer(), name)
+  subtree:add(f.prefix, buffer(0, 1))
+  subtree:add(command_field, buffer(1, 1))
+
+  local details = {}
+  local length = 0
+  for _, param in ipairs(params[id] or {}) do
+    if param.kind == "data" then
+      local available = math.max(buffer:len() - param.offset, 0)
+      local size = math.min(length, available)
+      if size > 0 then
+        subtree:add(param.field, buffer(param.offset, size))
+      end
+    elseif param.kind == "magic" then
+      local fits = param.offset + param.size <= buffer:len()
+      if fits then
+        subtree:add(param.field, buffer(param.offset, param.size))
+      end
+      if not fits or not matches(buffer(param.offset, param.size), param.magic) then
+        -- Without the magic bytes, the controller ignores the command, so
+        -- the other parameters mean nothing
+        details = { "(bad magic)" }
+        break
+      end
+    elseif param.offset + param.size <= buffer:len() then
+      local range = buffer(param.offset, param.size)
+      subtree:add(param.field, range)
+      if param.name == "length" then
+        length = range:uint()
+      end
+      table.insert(details, param.name .. "=" .. format_param(param, range))
+    else
+      table.insert(details, param.name .. "=?")
+    end
+  end
+
+  local info = name
+  if #details > 0 then
+    info = info .. " " .. table.concat(details, " ")
+  end
+  pinfo.cols.info = info
+  subtree:append_text(": " .. info)
+end
+
+local function dissect_input(buffer, pinfo, tree)
+  pinfo.cols.info = "InputReport"
+  local subtree = tree:add(proto, buffer(), "InputReport")
+  subtree:add(f.prefix, buffer(0, 1))
+  for _, input in ipairs(input_fields) do
+    subtree:add(input.field, buffer(input.offset, input.size))
+  end
+end
+
+function proto.dissector(buffer, pinfo, tree)
+  if buffer:len() < 2 then
+    return 0
+  end
+  pinfo.cols.protocol = proto.name
+
+  local prefix = buffer(0, 1):uint()
+  if prefix == {HOST_PREFIX} or prefix == {DEVICE_PREFIX} then
+    dissect_config(buffer, pinfo, tree, prefix)
+  elseif prefix == {INPUT_REPORT_ID} and buffer:len() >= {INPUT_REPORT_LEN} then
+    dissect_input(buffer, pinfo, tree)
+  else
+    return 0
+  end
+  return buffer:len()
+end
+
+-- HID reports are recognised by their size and first byte, as the USB
+-- dissector doesn't say which device a transfer belongs to
+local function heuristic(buffer, pinfo, tree)
+  if buffer:len() ~= {REPORT_SIZE} then
+    return false
+  end
+  local prefix = buffer(0, 1):uint()
+  local id = buffer(1, 1):uint()
+  if (prefix == {HOST_PREFIX} and host_commands[id])
+      or (prefix == {DEVICE_PREFIX} and device_commands[id])
+      or prefix == {INPUT_REPORT_ID} then
+    proto.dissector(buffer, pinfo, tree)
+    return true
+  end
+  return false
+end
+
+proto:register_heuristic("usb.interrupt", heuristic)
+proto:register_heuristic("usb.control", heuristic)
+"#;
@@ -1,37 +1,180 @@
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod backup;
 mod device;
+pub mod dry_run;
+pub mod hex;
+mod keycode;
+pub mod layout;
 mod profile;
+pub mod protocol;
+pub mod registry;
+pub mod simulator;
+pub mod trace;
+pub mod transport;
 
+use std::fmt;
 use std::io::{Cursor, Write};
+use std::ops::Range;
 use std::time::Duration;
 
-use eyre::ensure;
+use eyre::{bail, ensure, eyre};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use tracing::debug;
 
-use crate::driver::device::{Device, TimeoutError};
-use crate::hid::Hid;
+use crate::driver::backup::{Backup, FORMAT_VERSION, ProfileData};
+use crate::driver::device::Device;
+use crate::driver::protocol::VIBRATION_MAGIC;
+use crate::driver::transport::{TimeoutError, Transport};
+use crate::hid::{DeviceInfo, Hid};
 
+pub use keycode::KeyCode;
+pub use layout::Layout;
 pub use profile::*;
 
-pub struct Cyclone2<'a> {
-    device: Device<'a>,
+const VENDOR_ID: u16 = 0x3537;
+
+/// Called with the current bytes of a profile before they are overwritten.
+pub type WriteHook = Box<dyn Fn(ProfileId, &[u8]) -> eyre::Result<()> + Send + Sync>;
+
+/// A connection to a controller, which can be shared between threads.
+pub struct Cyclone2 {
+    transport: Box<dyn Transport>,
+    variant: Variant,
+    write_hook: Option<WriteHook>,
+    /// Held while waiting for the reply to a command, so that commands sent
+    /// from different threads get their own replies.
+    exchange: Mutex<()>,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FirmwareVersion {
     pub controller: String,
     pub dongle: String,
 }
 
-impl<'a> Cyclone2<'a> {
-    pub fn connect(hid: &'a Hid) -> eyre::Result<Cyclone2<'a>> {
-        Ok(Cyclone2 {
-            device: Device::connect(hid, 0x3537, 0x100b)?,
+/// Hardware variants of the controller, which have different product IDs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Wired,
+    Wireless,
+    WiredAdc,
+    WirelessAlt,
+    Pro,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 5] = [
+        Variant::Wired,
+        Variant::Wireless,
+        Variant::WiredAdc,
+        Variant::WirelessAlt,
+        Variant::Pro,
+    ];
+
+    pub fn product_id(&self) -> u16 {
+        match self {
+            Variant::Wired => 0x101d,
+            Variant::Wireless => 0x102a,
+            Variant::WiredAdc => 0x1053,
+            Variant::WirelessAlt => 0x100b,
+            Variant::Pro => 0x1050,
+        }
+    }
+
+    pub fn from_product_id(product_id: u16) -> Option<Variant> {
+        Variant::ALL
+            .into_iter()
+            .find(|v| v.product_id() == product_id)
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Variant::Wired => "C2 Wired",
+            Variant::Wireless => "C2 Wireless",
+            Variant::WiredAdc => "C2 Wired ADC",
+            Variant::WirelessAlt => "C2 Wireless (alt dongle)",
+            Variant::Pro => "C2 Pro",
         })
     }
+}
+
+impl Cyclone2 {
+    /// Connects to the first controller found, trying each known variant.
+    pub fn connect(hid: &Hid) -> eyre::Result<Cyclone2> {
+        let (transport, variant) = Cyclone2::open_transport(hid)?;
+        Ok(Cyclone2::with_transport(transport, variant))
+    }
+
+    /// Opens the first controller found without wrapping it, so that the
+    /// transport can be wrapped before being passed to [`with_transport`].
+    ///
+    /// [`with_transport`]: Cyclone2::with_transport
+    pub fn open_transport(hid: &Hid) -> eyre::Result<(Box<dyn Transport>, Variant)> {
+        Cyclone2::open_transport_matching(hid, |_| true)
+    }
+
+    /// Connects to the first controller whose HID interfaces are accepted by
+    /// `filter`, such as to pick one of several by serial number.
+    pub fn connect_matching(
+        hid: &Hid,
+        filter: impl Fn(&DeviceInfo) -> bool,
+    ) -> eyre::Result<Cyclone2> {
+        let (transport, variant) = Cyclone2::open_transport_matching(hid, filter)?;
+        Ok(Cyclone2::with_transport(transport, variant))
+    }
+
+    /// Version of [`open_transport`] that only considers the HID interfaces
+    /// accepted by `filter`.
+    ///
+    /// [`open_transport`]: Cyclone2::open_transport
+    pub fn open_transport_matching(
+        hid: &Hid,
+        filter: impl Fn(&DeviceInfo) -> bool,
+    ) -> eyre::Result<(Box<dyn Transport>, Variant)> {
+        for variant in Variant::ALL {
+            match Device::connect(hid, VENDOR_ID, variant.product_id(), &filter) {
+                Ok(device) => return Ok((Box::new(device), variant)),
+                Err(e) => debug!("Failed to open {variant}: {e}"),
+            }
+        }
+        bail!("no controller found")
+    }
+
+    pub fn with_transport(transport: Box<dyn Transport>, variant: Variant) -> Cyclone2 {
+        Cyclone2 {
+            transport,
+            variant,
+            write_hook: None,
+            exchange: Mutex::new(()),
+        }
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Sets a function to be called with the current bytes of a profile
+    /// before every write to it, such as to keep a copy. The write is not
+    /// made if the hook fails.
+    pub fn set_write_hook(
+        &mut self,
+        hook: impl Fn(ProfileId, &[u8]) -> eyre::Result<()> + Send + Sync + 'static,
+    ) {
+        self.write_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_write_hook(&mut self) {
+        self.write_hook = None;
+    }
 
     pub fn get_firmware_version(&self) -> eyre::Result<FirmwareVersion> {
         let res = self.write_acked_with_retry(&[0x0f, 0x09])?;
 
-        ensure!(&res[0..2] == &[0x10, 0x0a]);
+        ensure!(res[0..2] == [0x10, 0x0a]);
 
         let controller_version = str::from_utf8(&res[4..=8])?.replace('\0', ".");
         let dongle_version = str::from_utf8(&res[12..=16])?.replace('\0', ".");
@@ -42,10 +185,39 @@ impl<'a> Cyclone2<'a> {
         })
     }
 
+    pub fn get_current_profile(&self) -> eyre::Result<ProfileId> {
+        let res = self.write_acked_with_retry(&[0x0f, 0x0b])?;
+
+        ensure!(res[0..2] == [0x10, 0x0c]);
+
+        // 0 is sent before a profile has ever been selected
+        let index = res[2].max(1);
+        ProfileId::from_index(index).ok_or_else(|| eyre!("invalid current profile: {index}"))
+    }
+
+    pub fn switch_profile(&self, id: ProfileId) -> eyre::Result<()> {
+        ensure!(
+            id != ProfileId::Light,
+            "the light profile cannot be made active"
+        );
+
+        let res = self.write_acked_with_retry(&[0x0f, 0x07, id.index()])?;
+
+        ensure!(res[0..2] == [0x10, 0x06]);
+
+        Ok(())
+    }
+
+    /// Runs the rumble motors at the given strengths, where 0 is off.
+    pub fn vibrate(&self, left: u8, right: u8) -> eyre::Result<()> {
+        let [magic0, magic1] = VIBRATION_MAGIC;
+        self.transport
+            .write(&[0x0f, 0x20, magic0, magic1, left, right])
+    }
+
     pub fn get_control_profile(&self, num: ProfileNum) -> eyre::Result<ControlProfile> {
-        let profile_bytes = self.read_profile(ProfileId::Num(num), 680)?;
-        let mut cursor = Cursor::new(&profile_bytes);
-        ControlProfile::read(&mut cursor)
+        let profile_bytes = self.read_profile(ProfileId::Num(num), ControlProfile::SIZE)?;
+        ControlProfile::read(&mut profile_bytes.as_slice())
     }
 
     pub fn set_control_profile(
@@ -53,24 +225,169 @@ impl<'a> Cyclone2<'a> {
         num: ProfileNum,
         profile: &ControlProfile,
     ) -> eyre::Result<()> {
-        let mut bytes = Vec::with_capacity(680);
+        let mut bytes = Vec::with_capacity(ControlProfile::SIZE);
         profile.write(&mut bytes)?;
         self.write_profile(ProfileId::Num(num), &bytes)
     }
 
     pub fn get_light_profile(&self) -> eyre::Result<LightProfile> {
-        let profile_bytes = self.read_profile(ProfileId::Light, 635)?;
-        let mut cursor = Cursor::new(&profile_bytes);
-        LightProfile::read(&mut cursor)
+        let profile_bytes = self.read_profile(ProfileId::Light, LightProfile::SIZE)?;
+        LightProfile::read(&mut profile_bytes.as_slice())
     }
 
     pub fn set_light_profile(&self, profile: &LightProfile) -> eyre::Result<()> {
-        let mut bytes = Vec::with_capacity(635);
+        let mut bytes = Vec::with_capacity(LightProfile::SIZE);
         profile.write(&mut bytes)?;
         self.write_profile(ProfileId::Light, &bytes)
     }
 
-    fn read_profile(&self, id: ProfileId, size: usize) -> eyre::Result<Vec<u8>> {
+    /// Reads every profile, along with what is needed to restore them later.
+    pub fn backup(&self) -> eyre::Result<Backup> {
+        let profiles = ProfileId::ALL
+            .into_iter()
+            .map(|id| {
+                Ok(ProfileData {
+                    id,
+                    bytes: self.read_profile(id, id.kind().size())?,
+                })
+            })
+            .collect::<eyre::Result<_>>()?;
+
+        Ok(Backup {
+            version: FORMAT_VERSION,
+            product_id: self.variant.product_id(),
+            firmware: self.get_firmware_version()?,
+            current_profile: self.get_current_profile()?,
+            profiles,
+        })
+    }
+
+    /// Writes every profile in a backup, reading each back to check that it
+    /// was stored, then reselects the profile that was active.
+    ///
+    /// Use [`Backup::check_compatible`] first to make sure the backup was
+    /// taken from a matching controller.
+    pub fn restore(&self, backup: &Backup) -> eyre::Result<()> {
+        backup.validate()?;
+
+        for profile in &backup.profiles {
+            self.write_profile(profile.id, &profile.bytes)?;
+            let stored = self.read_profile(profile.id, profile.bytes.len())?;
+            ensure!(
+                stored == profile.bytes,
+                "profile {} did not verify after writing",
+                profile.id
+            );
+        }
+
+        self.switch_profile(backup.current_profile)
+    }
+
+    /// Copies a control profile to another slot, optionally giving the copy a
+    /// new name.
+    pub fn copy_profile(
+        &self,
+        from: ProfileId,
+        to: ProfileId,
+        name: Option<&str>,
+    ) -> eyre::Result<()> {
+        ensure!(
+            from.kind() == ProfileKind::Control && to.kind() == ProfileKind::Control,
+            "only control profiles can be copied"
+        );
+        ensure!(from != to, "cannot copy a profile onto itself");
+
+        let old = self.read_profile(to, ControlProfile::SIZE)?;
+        let mut new = self.read_profile(from, ControlProfile::SIZE)?;
+        if let Some(name) = name {
+            let mut view = ControlProfile::view_mut(&mut new);
+            ensure!(
+                name.len() <= ControlProfile::MAX_NAME_LEN,
+                "names can be at most {} bytes",
+                ControlProfile::MAX_NAME_LEN
+            );
+            view.name().set(name)?;
+        }
+
+        self.update_profile(to, &old, &new)
+    }
+
+    /// Swaps two control profiles. Profiles whose `shift_value` refers to
+    /// either slot are updated to follow the move, and are returned.
+    pub fn swap_profiles(&self, a: ProfileId, b: ProfileId) -> eyre::Result<Vec<ProfileId>> {
+        ensure!(
+            a.kind() == ProfileKind::Control && b.kind() == ProfileKind::Control,
+            "only control profiles can be swapped"
+        );
+        ensure!(a != b, "cannot swap a profile with itself");
+
+        let ids: Vec<_> = ProfileId::ALL
+            .into_iter()
+            .filter(|id| id.kind() == ProfileKind::Control)
+            .collect();
+        let old = ids
+            .iter()
+            .map(|id| self.read_profile(*id, ControlProfile::SIZE))
+            .collect::<eyre::Result<Vec<_>>>()?;
+
+        let mut new = old.clone();
+        let a_pos = ids.iter().position(|id| *id == a).unwrap();
+        let b_pos = ids.iter().position(|id| *id == b).unwrap();
+        new.swap(a_pos, b_pos);
+
+        let mut retargeted = Vec::new();
+        for (id, bytes) in ids.iter().zip(&mut new) {
+            let mut view = ControlProfile::view_mut(bytes);
+            let mut shift_value = view.shift_value();
+            let target = shift_value.get()?;
+            let moved = if target == a.index() {
+                b.index()
+            } else if target == b.index() {
+                a.index()
+            } else {
+                continue;
+            };
+            shift_value.set(&moved)?;
+            retargeted.push(*id);
+        }
+
+        for ((id, old), new) in ids.iter().zip(&old).zip(&new) {
+            self.update_profile(*id, old, new)?;
+        }
+
+        Ok(retargeted)
+    }
+
+    /// Writes only the bytes that differ between `old` and `new`, which should
+    /// be encodings of the same profile. Each run of changed bytes is written
+    /// separately, so bytes between them are left alone.
+    pub fn update_profile(&self, id: ProfileId, old: &[u8], new: &[u8]) -> eyre::Result<()> {
+        ensure!(old.len() == new.len(), "profile sizes differ");
+        ensure!(
+            new.len() <= id.kind().size(),
+            "profile {id} is only {} bytes",
+            id.kind().size()
+        );
+
+        let ranges = changed_ranges(old, new);
+        if ranges.is_empty() {
+            return Ok(());
+        }
+
+        self.run_write_hook(id)?;
+        for range in ranges {
+            self.write_chunks(id, range.start, &new[range])?;
+        }
+        Ok(())
+    }
+
+    pub fn read_profile(&self, id: ProfileId, size: usize) -> eyre::Result<Vec<u8>> {
+        ensure!(
+            size <= id.kind().size(),
+            "profile {id} is only {} bytes",
+            id.kind().size()
+        );
+
         let profile_size = size as u16;
 
         let chunk_size = 58u16;
@@ -98,8 +415,8 @@ impl<'a> Cyclone2<'a> {
 
             let res = self.write_acked_with_retry(req)?;
 
-            ensure!(&res[0..2] == &[0x10, 0x05]);
-            ensure!(&res[2..6] == &req[2..6]);
+            ensure!(res[0..2] == [0x10, 0x05]);
+            ensure!(res[2..6] == req[2..6]);
 
             profile_bytes.extend_from_slice(&res[6..6 + chunk_size as usize]);
         }
@@ -108,13 +425,46 @@ impl<'a> Cyclone2<'a> {
     }
 
     fn write_profile(&self, id: ProfileId, bytes: &[u8]) -> eyre::Result<()> {
+        self.write_profile_range(id, 0, bytes)
+    }
+
+    /// Writes `bytes` to a profile, starting at `offset`.
+    pub fn write_profile_range(
+        &self,
+        id: ProfileId,
+        offset: usize,
+        bytes: &[u8],
+    ) -> eyre::Result<()> {
+        ensure!(
+            offset + bytes.len() <= id.kind().size(),
+            "cannot write {} bytes at offset {offset} of profile {id}, which is only {} bytes",
+            bytes.len(),
+            id.kind().size()
+        );
+
+        self.run_write_hook(id)?;
+        self.write_chunks(id, offset, bytes)
+    }
+
+    /// Passes the profile's current contents to the write hook, if there is
+    /// one, before it's changed.
+    fn run_write_hook(&self, id: ProfileId) -> eyre::Result<()> {
+        if let Some(hook) = &self.write_hook {
+            let current = self.read_profile(id, id.kind().size())?;
+            hook(id, &current)?;
+        }
+        Ok(())
+    }
+
+    fn write_chunks(&self, id: ProfileId, offset: usize, bytes: &[u8]) -> eyre::Result<()> {
         let profile_size = bytes.len();
 
         let chunk_size = 58usize;
         let chunk_count = profile_size.div_ceil(chunk_size);
 
         for i in 0..chunk_count {
-            let byte_offset = chunk_size * i;
+            let start = chunk_size * i;
+            let byte_offset = offset + start;
 
             let chunk_size = if i == chunk_count - 1 {
                 profile_size - chunk_size * i
@@ -134,22 +484,52 @@ impl<'a> Cyclone2<'a> {
                 chunk_size as u8,
             ])?;
 
-            cursor.write_all(&bytes[byte_offset..byte_offset + chunk_size])?;
+            cursor.write_all(&bytes[start..start + chunk_size])?;
 
             let res = self.write_acked_with_retry(&cmd)?;
 
-            ensure!(&res[0..2] == &[0x10, 0x06]);
+            ensure!(res[0..2] == [0x10, 0x06]);
         }
 
         Ok(())
     }
 
+    /// Sends a report as-is without waiting for a reply, for exploring the
+    /// protocol. The write hook is not called.
+    pub fn write_raw(&self, report: &[u8]) -> eyre::Result<()> {
+        self.transport.write(report)
+    }
+
+    /// Waits up to `timeout` for the next report from the controller.
+    pub fn read_raw(&self, timeout: Duration) -> eyre::Result<Option<[u8; 64]>> {
+        match self.transport.read_timeout(timeout) {
+            Ok(res) => Ok(Some(res)),
+            Err(TimeoutError::Timeout) => Ok(None),
+            Err(TimeoutError::Other(e)) => Err(e),
+        }
+    }
+
+    /// Receives the input reports the controller sends as it's used, or
+    /// `None` if the transport doesn't get them. Each report goes to only one
+    /// receiver, and reports are dropped while nothing is receiving them.
+    pub fn input_reports(&self) -> Option<kanal::Receiver<[u8; 64]>> {
+        self.transport.input_reports()
+    }
+
+    /// Receives the `ProfileChanged` reports the controller sends when its
+    /// profile is changed on the controller itself, or `None` if the
+    /// transport doesn't get them.
+    pub fn notifications(&self) -> Option<kanal::Receiver<[u8; 64]>> {
+        self.transport.notifications()
+    }
+
     /// Sends a command, expecting an ack. If no ack is received within the
     /// time limit, the command is resent.
     fn write_acked_with_retry(&self, req: &[u8]) -> eyre::Result<[u8; 64]> {
+        let _exchange = self.exchange.lock();
         loop {
-            self.device.write(req)?;
-            match self.device.read_timeout(Duration::from_millis(200)) {
+            self.transport.write(req)?;
+            match self.transport.read_timeout(Duration::from_millis(200)) {
                 Ok(res) => return Ok(res),
                 Err(TimeoutError::Timeout) => debug!("Request timed out, retrying"),
                 Err(TimeoutError::Other(e)) => return Err(e),
@@ -157,3 +537,16 @@ impl<'a> Cyclone2<'a> {
         }
     }
 }
+
+/// Returns each run of bytes that differ between `old` and `new`, in order,
+/// which is what [`Cyclone2::update_profile`] writes.
+pub fn changed_ranges(old: &[u8], new: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (i, _) in old.iter().zip(new).enumerate().filter(|(_, (a, b))| a != b) {
+        match ranges.last_mut() {
+            Some(range) if range.end == i => range.end += 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
@@ -0,0 +1,167 @@
+//! An async interface to the controller, for programs running on tokio.
+//!
+//! Commands still wait for the controller's replies, so each one runs on
+//! tokio's blocking thread pool. Input reports and notifications are read
+//! from the reader thread's channels without blocking.
+
+use std::sync::Arc;
+
+use futures_util::{Stream, stream};
+use tokio::task;
+
+use crate::driver::backup::Backup;
+use crate::driver::{
+    ControlProfile, Cyclone2, FirmwareVersion, LightProfile, ProfileId, ProfileNum, Variant,
+};
+use crate::hid::Hid;
+
+/// A [`Cyclone2`] whose methods return futures. Clones share the same
+/// connection.
+#[derive(Clone)]
+pub struct AsyncCyclone2 {
+    inner: Arc<Cyclone2>,
+}
+
+impl AsyncCyclone2 {
+    /// Connects to the first controller found, trying each known variant.
+    pub async fn connect(hid: Hid) -> eyre::Result<AsyncCyclone2> {
+        let c2 = task::spawn_blocking(move || Cyclone2::connect(&hid)).await??;
+        Ok(AsyncCyclone2::new(c2))
+    }
+
+    /// Wraps a connection, such as one whose transport or write hook has
+    /// already been set up.
+    pub fn new(c2: Cyclone2) -> AsyncCyclone2 {
+        AsyncCyclone2 {
+            inner: Arc::new(c2),
+        }
+    }
+
+    /// The underlying connection, for calls made outside of async code.
+    pub fn blocking(&self) -> &Cyclone2 {
+        &self.inner
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.inner.variant()
+    }
+
+    pub async fn get_firmware_version(&self) -> eyre::Result<FirmwareVersion> {
+        self.run(|c2| c2.get_firmware_version()).await
+    }
+
+    pub async fn get_current_profile(&self) -> eyre::Result<ProfileId> {
+        self.run(|c2| c2.get_current_profile()).await
+    }
+
+    pub async fn switch_profile(&self, id: ProfileId) -> eyre::Result<()> {
+        self.run(move |c2| c2.switch_profile(id)).await
+    }
+
+    /// Runs the rumble motors at the given strengths, where 0 is off.
+    pub async fn vibrate(&self, left: u8, right: u8) -> eyre::Result<()> {
+        self.run(move |c2| c2.vibrate(left, right)).await
+    }
+
+    pub async fn get_control_profile(&self, num: ProfileNum) -> eyre::Result<ControlProfile> {
+        self.run(move |c2| c2.get_control_profile(num)).await
+    }
+
+    pub async fn set_control_profile(
+        &self,
+        num: ProfileNum,
+        profile: ControlProfile,
+    ) -> eyre::Result<()> {
+        self.run(move |c2| c2.set_control_profile(num, &profile))
+            .await
+    }
+
+    pub async fn get_light_profile(&self) -> eyre::Result<LightProfile> {
+        self.run(|c2| c2.get_light_profile()).await
+    }
+
+    pub async fn set_light_profile(&self, profile: LightProfile) -> eyre::Result<()> {
+        self.run(move |c2| c2.set_light_profile(&profile)).await
+    }
+
+    /// See [`Cyclone2::backup`].
+    pub async fn backup(&self) -> eyre::Result<Backup> {
+        self.run(|c2| c2.backup()).await
+    }
+
+    /// See [`Cyclone2::restore`].
+    pub async fn restore(&self, backup: Backup) -> eyre::Result<()> {
+        self.run(move |c2| c2.restore(&backup)).await
+    }
+
+    /// See [`Cyclone2::copy_profile`].
+    pub async fn copy_profile(
+        &self,
+        from: ProfileId,
+        to: ProfileId,
+        name: Option<String>,
+    ) -> eyre::Result<()> {
+        self.run(move |c2| c2.copy_profile(from, to, name.as_deref()))
+            .await
+    }
+
+    /// See [`Cyclone2::swap_profiles`].
+    pub async fn swap_profiles(&self, a: ProfileId, b: ProfileId) -> eyre::Result<Vec<ProfileId>> {
+        self.run(move |c2| c2.swap_profiles(a, b)).await
+    }
+
+    /// See [`Cyclone2::update_profile`].
+    pub async fn update_profile(
+        &self,
+        id: ProfileId,
+        old: Vec<u8>,
+        new: Vec<u8>,
+    ) -> eyre::Result<()> {
+        self.run(move |c2| c2.update_profile(id, &old, &new)).await
+    }
+
+    pub async fn read_profile(&self, id: ProfileId, size: usize) -> eyre::Result<Vec<u8>> {
+        self.run(move |c2| c2.read_profile(id, size)).await
+    }
+
+    /// Writes `bytes` to a profile, starting at `offset`.
+    pub async fn write_profile_range(
+        &self,
+        id: ProfileId,
+        offset: usize,
+        bytes: Vec<u8>,
+    ) -> eyre::Result<()> {
+        self.run(move |c2| c2.write_profile_range(id, offset, &bytes))
+            .await
+    }
+
+    /// The input reports the controller sends as it's used, or `None` if the
+    /// transport doesn't get them. See [`Cyclone2::input_reports`].
+    pub fn input_reports(&self) -> Option<impl Stream<Item = [u8; 64]> + Send + 'static> {
+        Some(receive(self.inner.input_reports()?))
+    }
+
+    /// The `ProfileChanged` reports the controller sends, or `None` if the
+    /// transport doesn't get them. See [`Cyclone2::notifications`].
+    pub fn notifications(&self) -> Option<impl Stream<Item = [u8; 64]> + Send + 'static> {
+        Some(receive(self.inner.notifications()?))
+    }
+
+    async fn run<T, F>(&self, f: F) -> eyre::Result<T>
+    where
+        F: FnOnce(&Cyclone2) -> eyre::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let c2 = self.inner.clone();
+        task::spawn_blocking(move || f(&c2)).await?
+    }
+}
+
+/// Yields the reports sent on a channel, ending once the connection is
+/// closed.
+fn receive(receiver: kanal::Receiver<[u8; 64]>) -> impl Stream<Item = [u8; 64]> + Send + 'static {
+    stream::unfold(receiver.to_async(), |receiver| async move {
+        let report = receiver.recv().await.ok()?;
+        Some((report, receiver))
+    })
+}
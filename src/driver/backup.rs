@@ -0,0 +1,109 @@
+//! Archives of every profile stored on a controller, along with enough about
+//! the controller to tell whether the archive can be restored onto another.
+
+use eyre::{WrapErr, bail, ensure};
+use serde::{Deserialize, Serialize};
+
+use crate::driver::{FirmwareVersion, ProfileId, Variant};
+
+/// Version of the archive format written by this build.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u32,
+    pub product_id: u16,
+    pub firmware: FirmwareVersion,
+    pub current_profile: ProfileId,
+    pub profiles: Vec<ProfileData>,
+}
+
+/// The raw encoding of one profile.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProfileData {
+    pub id: ProfileId,
+    #[serde(with = "crate::driver::hex")]
+    pub bytes: Vec<u8>,
+}
+
+impl Backup {
+    pub fn from_json(json: &str) -> eyre::Result<Backup> {
+        #[derive(Deserialize)]
+        struct Header {
+            version: u32,
+        }
+
+        // Check the version first, so that archives from newer builds are
+        // reported as such rather than as malformed
+        let header: Header = serde_json::from_str(json).wrap_err("not a backup archive")?;
+        ensure!(
+            header.version == FORMAT_VERSION,
+            "unsupported backup version {}, expected {FORMAT_VERSION}",
+            header.version
+        );
+
+        let backup: Backup = serde_json::from_str(json).wrap_err("malformed backup archive")?;
+        backup.validate()?;
+        Ok(backup)
+    }
+
+    pub fn to_json(&self) -> eyre::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn variant(&self) -> Option<Variant> {
+        Variant::from_product_id(self.product_id)
+    }
+
+    pub fn profile(&self, id: ProfileId) -> Option<&[u8]> {
+        self.profiles
+            .iter()
+            .find(|p| p.id == id)
+            .map(|p| p.bytes.as_slice())
+    }
+
+    /// Checks that every profile is present exactly once and decodes.
+    pub fn validate(&self) -> eyre::Result<()> {
+        for id in ProfileId::ALL {
+            let count = self.profiles.iter().filter(|p| p.id == id).count();
+            ensure!(
+                count == 1,
+                "expected one copy of profile {id}, found {count}"
+            );
+        }
+        for profile in &self.profiles {
+            profile
+                .id
+                .kind()
+                .validate(&profile.bytes)
+                .wrap_err_with(|| format!("profile {} is invalid", profile.id))?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the backup was taken from the same variant of controller,
+    /// running the same firmware.
+    pub fn check_compatible(
+        &self,
+        variant: Variant,
+        firmware: &FirmwareVersion,
+    ) -> eyre::Result<()> {
+        if self.product_id != variant.product_id() {
+            let taken_from = match self.variant() {
+                Some(variant) => variant.to_string(),
+                None => format!("an unknown controller ({:#06x})", self.product_id),
+            };
+            bail!("backup was taken from {taken_from}, but this is {variant}");
+        }
+        if self.firmware != *firmware {
+            bail!(
+                "backup was taken with firmware {}/{}, but this controller runs {}/{}",
+                self.firmware.controller,
+                self.firmware.dongle,
+                firmware.controller,
+                firmware.dongle
+            );
+        }
+        Ok(())
+    }
+}
@@ -1,55 +1,91 @@
 use std::thread;
 use std::time::Duration;
 
-use eyre::eyre;
+use eyre::{OptionExt, eyre};
+use tracing::debug;
 
-use crate::hid::{Hid, HidDevice};
+use crate::driver::protocol::{
+    CONFIG_USAGE_PAGE, DEVICE_PREFIX, GAMEPAD_USAGE_PAGE, INPUT_REPORT_ID, PROFILE_CHANGED,
+};
+use crate::driver::transport::{TimeoutError, Transport};
+use crate::hid::{DeviceInfo, Hid, HidDevice, HidError, HidReadDevice};
 
-pub struct Device<'a> {
-    hid_device: HidDevice<'a>,
+/// Most reports of one kind kept for a receiver that isn't keeping up.
+/// Later ones are dropped until there is room.
+const QUEUE_LEN: usize = 64;
+
+pub struct Device {
+    hid_device: HidDevice,
     read_receiver: kanal::Receiver<[u8; 64]>,
+    input_receiver: kanal::Receiver<[u8; 64]>,
+    notification_receiver: kanal::Receiver<[u8; 64]>,
     closed_receiver: kanal::Receiver<()>,
+    readers: usize,
+    _gamepad: Option<HidDevice>,
 }
 
-impl<'a> Device<'a> {
-    pub fn connect(hid: &'a Hid, vendor_id: u16, product_id: u16) -> eyre::Result<Device<'a>> {
-        let device = hid.open(vendor_id, product_id)?;
-        let read_device = device.reader();
-
-        let (read_sender, read_receiver) = kanal::unbounded();
-        let (closed_sender, closed_receiver) = kanal::bounded(1);
-
-        thread::spawn(move || {
-            while !read_sender.is_closed() {
-                let mut buf = [0u8; 64];
-
-                let Ok(res) = read_device.read_timeout(&mut buf, Duration::from_millis(100)) else {
-                    break;
-                };
-
-                if res == 0 {
-                    continue;
-                }
-
-                assert_eq!(res, 64);
-
-                if buf[0] == 18 {
-                    // TODO: Handle state messages
-                    continue;
-                }
+/// Where a reader thread sends each kind of report.
+#[derive(Clone)]
+struct Routes {
+    replies: kanal::Sender<[u8; 64]>,
+    inputs: kanal::Sender<[u8; 64]>,
+    notifications: kanal::Sender<[u8; 64]>,
+}
 
-                if let Err(_) = read_sender.send(buf) {
-                    break;
-                }
-            }
+impl Device {
+    /// Opens the controller with the given IDs, considering only the HID
+    /// interfaces accepted by `filter`.
+    pub fn connect(
+        hid: &Hid,
+        vendor_id: u16,
+        product_id: u16,
+        filter: &dyn Fn(&DeviceInfo) -> bool,
+    ) -> eyre::Result<Device> {
+        let mut devices = hid.enumerate(vendor_id, product_id)?;
+        devices.retain(|info| filter(info));
+        if devices.is_empty() {
+            return Err(HidError::NotFound.into());
+        }
+        // The controller has a gamepad interface too, with the same IDs
+        let info = devices
+            .iter()
+            .find(|info| info.usage_page == CONFIG_USAGE_PAGE)
+            .ok_or_eyre("controller has no configuration interface")?;
+        let device = hid.open_path(&info.path)?;
+        // Configuration doesn't need input reports, so the controller is
+        // still usable if the gamepad interface can't be opened
+        let gamepad = devices
+            .iter()
+            .find(|gamepad| gamepad.usage_page == GAMEPAD_USAGE_PAGE && gamepad.path != info.path)
+            .and_then(|gamepad| {
+                hid.open_path(&gamepad.path)
+                    .inspect_err(|e| debug!("Not reading input reports: {e}"))
+                    .ok()
+            });
 
-            let _ = closed_sender.send(());
-        });
+        let (read_sender, read_receiver) = kanal::unbounded();
+        let (input_sender, input_receiver) = kanal::bounded(QUEUE_LEN);
+        let (notification_sender, notification_receiver) = kanal::bounded(QUEUE_LEN);
+        let (closed_sender, closed_receiver) = kanal::bounded(2);
+        let routes = Routes {
+            replies: read_sender,
+            inputs: input_sender,
+            notifications: notification_sender,
+        };
+
+        spawn_reader(device.reader(), routes.clone(), closed_sender.clone());
+        if let Some(gamepad) = &gamepad {
+            spawn_reader(gamepad.reader(), routes, closed_sender);
+        }
 
         Ok(Device {
             hid_device: device,
             read_receiver,
+            input_receiver,
+            notification_receiver,
             closed_receiver,
+            readers: 1 + usize::from(gamepad.is_some()),
+            _gamepad: gamepad,
         })
     }
 
@@ -78,14 +114,74 @@ impl<'a> Device<'a> {
     }
 }
 
-pub enum TimeoutError {
-    Timeout,
-    Other(eyre::Report),
+impl Transport for Device {
+    fn write(&self, data: &[u8]) -> eyre::Result<()> {
+        Device::write(self, data)
+    }
+
+    fn read_timeout(&self, timeout: Duration) -> Result<[u8; 64], TimeoutError> {
+        Device::read_timeout(self, timeout)
+    }
+
+    fn input_reports(&self) -> Option<kanal::Receiver<[u8; 64]>> {
+        Some(self.input_receiver.clone())
+    }
+
+    fn notifications(&self) -> Option<kanal::Receiver<[u8; 64]>> {
+        Some(self.notification_receiver.clone())
+    }
+}
+
+/// Reads reports from `device` until the device is dropped, sending each
+/// to the receiver for its kind.
+fn spawn_reader(device: HidReadDevice, routes: Routes, closed_sender: kanal::Sender<()>) {
+    thread::spawn(move || {
+        while !routes.replies.is_closed() {
+            let mut buf = [0u8; 64];
+
+            let Ok(res) = device.read_timeout(&mut buf, Duration::from_millis(100)) else {
+                break;
+            };
+
+            if res == 0 {
+                continue;
+            }
+
+            if res != buf.len() {
+                // Every report the controller sends is 64 bytes, so
+                // anything else is garbage
+                debug!("Ignoring {res}-byte report");
+                continue;
+            }
+
+            // Input reports and notifications come whether or not anyone is
+            // listening, so they're dropped rather than queued without limit
+            match buf {
+                [INPUT_REPORT_ID, ..] => {
+                    let _ = routes.inputs.try_send(buf);
+                }
+                [DEVICE_PREFIX, PROFILE_CHANGED, ..] => {
+                    let _ = routes.notifications.try_send(buf);
+                }
+                _ => {
+                    if routes.replies.send(buf).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let _ = closed_sender.send(());
+    });
 }
 
-impl Drop for Device<'_> {
+impl Drop for Device {
     fn drop(&mut self) {
         let _ = self.read_receiver.close();
-        let _ = self.closed_receiver.recv();
+        let _ = self.input_receiver.close();
+        let _ = self.notification_receiver.close();
+        for _ in 0..self.readers {
+            let _ = self.closed_receiver.recv();
+        }
     }
 }
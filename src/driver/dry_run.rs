@@ -0,0 +1,118 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::driver::protocol::{self, DEVICE_PREFIX, Direction, HOST_PREFIX, REPORT_SIZE};
+use crate::driver::transport::{TimeoutError, Transport};
+
+type ReportCallback = Box<dyn Fn(&[u8; REPORT_SIZE]) + Send + Sync>;
+
+/// Transport that passes reads through to another transport, but reports
+/// every command that would change the controller instead of sending it.
+///
+/// Reported commands are answered as the controller would, and written bytes
+/// are kept so that reading them back gives the written values.
+pub struct DryRun {
+    inner: Box<dyn Transport>,
+    on_report: ReportCallback,
+    /// Bytes written to each profile, by profile index and offset.
+    written: Mutex<HashMap<(u8, usize), u8>>,
+    current_profile: Mutex<Option<u8>>,
+    replies: Mutex<VecDeque<[u8; REPORT_SIZE]>>,
+}
+
+impl DryRun {
+    /// Wraps `inner`, calling `on_report` with each report that is held back.
+    pub fn new(
+        inner: Box<dyn Transport>,
+        on_report: impl Fn(&[u8; REPORT_SIZE]) + Send + Sync + 'static,
+    ) -> DryRun {
+        DryRun {
+            inner,
+            on_report: Box::new(on_report),
+            written: Mutex::new(HashMap::new()),
+            current_profile: Mutex::new(None),
+            replies: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn reply(&self, bytes: &[u8]) {
+        let mut reply = [0; REPORT_SIZE];
+        reply[..bytes.len()].copy_from_slice(bytes);
+        self.replies.lock().push_back(reply);
+    }
+}
+
+impl Transport for DryRun {
+    fn write(&self, data: &[u8]) -> eyre::Result<()> {
+        let mut report = [0; REPORT_SIZE];
+        let len = data.len().min(REPORT_SIZE);
+        report[..len].copy_from_slice(&data[..len]);
+
+        let [HOST_PREFIX, id, ..] = report else {
+            (self.on_report)(&report);
+            return Ok(());
+        };
+
+        match id {
+            0x0b => match *self.current_profile.lock() {
+                Some(index) => self.reply(&[DEVICE_PREFIX, 0x0c, index]),
+                None => return self.inner.write(data),
+            },
+            _ if protocol::command(id)
+                .is_some_and(|c| c.direction == Direction::HostToDevice && !c.mutating) =>
+            {
+                return self.inner.write(data);
+            }
+            0x03 => {
+                let profile = report[2];
+                let offset = u16::from_be_bytes([report[3], report[4]]) as usize;
+                let length = (report[5] as usize).min(REPORT_SIZE - 6);
+                let mut written = self.written.lock();
+                for (i, byte) in report[6..6 + length].iter().enumerate() {
+                    written.insert((profile, offset + i), *byte);
+                }
+                (self.on_report)(&report);
+                self.reply(&[DEVICE_PREFIX, 0x06, 0]);
+            }
+            0x07 => {
+                *self.current_profile.lock() = Some(report[2]);
+                (self.on_report)(&report);
+                self.reply(&[DEVICE_PREFIX, 0x06, 0]);
+            }
+            // Other commands don't get a reply that the driver waits for
+            _ => (self.on_report)(&report),
+        }
+
+        Ok(())
+    }
+
+    fn read_timeout(&self, timeout: Duration) -> Result<[u8; 64], TimeoutError> {
+        if let Some(reply) = self.replies.lock().pop_front() {
+            return Ok(reply);
+        }
+
+        let mut res = self.inner.read_timeout(timeout)?;
+        if res[0..2] == [DEVICE_PREFIX, 0x05] {
+            let profile = res[2];
+            let offset = u16::from_be_bytes([res[3], res[4]]) as usize;
+            let length = (res[5] as usize).min(REPORT_SIZE - 6);
+            let written = self.written.lock();
+            for (i, byte) in res[6..6 + length].iter_mut().enumerate() {
+                if let Some(written) = written.get(&(profile, offset + i)) {
+                    *byte = *written;
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    fn input_reports(&self) -> Option<kanal::Receiver<[u8; 64]>> {
+        self.inner.input_reports()
+    }
+
+    fn notifications(&self) -> Option<kanal::Receiver<[u8; 64]>> {
+        self.inner.notifications()
+    }
+}
@@ -0,0 +1,28 @@
+//! Serialization of raw bytes as hex strings, for use with
+//! `#[serde(with = "crate::driver::hex")]`.
+
+use eyre::{ensure, eyre};
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    serializer.serialize_str(&hex)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    parse(&hex).map_err(serde::de::Error::custom)
+}
+
+pub fn parse(hex: &str) -> eyre::Result<Vec<u8>> {
+    ensure!(hex.len().is_multiple_of(2), "hex string has an odd length");
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            let byte = hex
+                .get(i..i + 2)
+                .ok_or_else(|| eyre!("invalid hex string"))?;
+            u8::from_str_radix(byte, 16).map_err(|_| eyre!("invalid hex byte: {byte}"))
+        })
+        .collect()
+}
@@ -0,0 +1,242 @@
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use eyre::eyre;
+
+use crate::driver::layout::{Layout, Shape, Value, ValueMut, ValueShape};
+
+/// A key or button that an input can be mapped to.
+///
+/// Codes without a known name are kept as-is and shown as numbers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct KeyCode(pub u8);
+
+const GAMEPAD_NAMES: [&str; 31] = [
+    "None",
+    "Up",
+    "Down",
+    "Left",
+    "Right",
+    "L1",
+    "R1",
+    "L3",
+    "R3",
+    "Cross",
+    "Circle",
+    "Square",
+    "Triangle",
+    "Home",
+    "Select",
+    "Start",
+    "Capture",
+    "FL1",
+    "FR1",
+    "L2",
+    "R2",
+    "LeftStickUp",
+    "LeftStickDown",
+    "LeftStickLeft",
+    "LeftStickRight",
+    "RightStickUp",
+    "RightStickDown",
+    "RightStickLeft",
+    "RightStickRight",
+    "LeftTouchpad",
+    "RightTouchpad",
+];
+
+// Keyboard keys start at code 50, in this order
+const KEYBOARD_NAMES: [&str; 99] = [
+    "KeyEsc",
+    "KeyF1",
+    "KeyF2",
+    "KeyF3",
+    "KeyF4",
+    "KeyF5",
+    "KeyF6",
+    "KeyF7",
+    "KeyF8",
+    "KeyF9",
+    "KeyF10",
+    "KeyF11",
+    "KeyF12",
+    "KeyGrave",
+    "Key1",
+    "Key2",
+    "Key3",
+    "Key4",
+    "Key5",
+    "Key6",
+    "Key7",
+    "Key8",
+    "Key9",
+    "Key0",
+    "KeyMinus",
+    "KeyEqual",
+    "KeyBackspace",
+    "KeyTab",
+    "KeyQ",
+    "KeyW",
+    "KeyE",
+    "KeyR",
+    "KeyT",
+    "KeyY",
+    "KeyU",
+    "KeyI",
+    "KeyO",
+    "KeyP",
+    "KeyLeftBracket",
+    "KeyRightBracket",
+    "KeyBackslash",
+    "KeyCapsLock",
+    "KeyA",
+    "KeyS",
+    "KeyD",
+    "KeyF",
+    "KeyG",
+    "KeyH",
+    "KeyJ",
+    "KeyK",
+    "KeyL",
+    "KeySemicolon",
+    "KeyApostrophe",
+    "KeyEnter",
+    "KeyLeftShift",
+    "KeyZ",
+    "KeyX",
+    "KeyC",
+    "KeyV",
+    "KeyB",
+    "KeyN",
+    "KeyM",
+    "KeyComma",
+    "KeyPeriod",
+    "KeySlash",
+    "KeyRightShift",
+    "KeyLeftCtrl",
+    "KeyLeftAlt",
+    "KeySpace",
+    "KeyRightAlt",
+    "KeyRightCtrl",
+    "KeyLeft",
+    "KeyUp",
+    "KeyDown",
+    "KeyRight",
+    "KeyInsert",
+    "KeyDelete",
+    "KeyHome",
+    "KeyEnd",
+    "KeyPageUp",
+    "KeyPageDown",
+    "KeyPrintScreen",
+    "KeyNumLock",
+    "KeyNum0",
+    "KeyNum1",
+    "KeyNum2",
+    "KeyNum3",
+    "KeyNum4",
+    "KeyNum5",
+    "KeyNum6",
+    "KeyNum7",
+    "KeyNum8",
+    "KeyNum9",
+    "KeyNumPeriod",
+    "KeyNumPlus",
+    "KeyNumMinus",
+    "KeyNumMultiply",
+    "KeyNumDivide",
+    "KeyNumEnter",
+];
+
+const KEYBOARD_START: u8 = 50;
+
+const OTHER_NAMES: [(u8, &str); 10] = [
+    (200, "MouseLeft"),
+    (201, "MouseMiddle"),
+    (202, "MouseRight"),
+    (203, "MouseForward"),
+    (204, "MouseBack"),
+    (205, "WheelUp"),
+    (206, "WheelDown"),
+    (230, "Mute"),
+    (231, "Shift"),
+    (255, "Disabled"),
+];
+
+impl KeyCode {
+    pub fn name(&self) -> Option<&'static str> {
+        let code = self.0;
+        if let Some(name) = GAMEPAD_NAMES.get(code as usize) {
+            return Some(name);
+        }
+        if let Some(name) = code
+            .checked_sub(KEYBOARD_START)
+            .and_then(|i| KEYBOARD_NAMES.get(i as usize))
+        {
+            return Some(name);
+        }
+        OTHER_NAMES
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, name)| *name)
+    }
+
+    pub fn from_name(name: &str) -> Option<KeyCode> {
+        let matches = |candidate: &&str| candidate.eq_ignore_ascii_case(name);
+        if let Some(i) = GAMEPAD_NAMES.iter().position(matches) {
+            return Some(KeyCode(i as u8));
+        }
+        if let Some(i) = KEYBOARD_NAMES.iter().position(matches) {
+            return Some(KeyCode(KEYBOARD_START + i as u8));
+        }
+        OTHER_NAMES
+            .iter()
+            .find(|(_, candidate)| matches(candidate))
+            .map(|(code, _)| KeyCode(*code))
+    }
+}
+
+impl fmt::Display for KeyCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl FromStr for KeyCode {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<KeyCode> {
+        if let Ok(code) = s.parse() {
+            return Ok(KeyCode(code));
+        }
+        KeyCode::from_name(s).ok_or_else(|| eyre!("unknown key: {s}"))
+    }
+}
+
+impl Layout for KeyCode {
+    const SIZE: usize = 1;
+    const SHAPE: &'static Shape = &Shape::Value(ValueShape::of::<KeyCode>("KeyCode"));
+
+    type View<'a> = Value<'a, KeyCode>;
+    type ViewMut<'a> = ValueMut<'a, KeyCode>;
+
+    fn read(reader: &mut impl Read) -> eyre::Result<KeyCode> {
+        Ok(KeyCode(u8::read(reader)?))
+    }
+
+    fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
+        self.0.write(writer)
+    }
+
+    fn view(bytes: &[u8]) -> Self::View<'_> {
+        Value::new(bytes)
+    }
+
+    fn view_mut(bytes: &mut [u8]) -> Self::ViewMut<'_> {
+        ValueMut::new(bytes)
+    }
+}
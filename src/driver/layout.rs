@@ -0,0 +1,479 @@
+use std::fmt::Display;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use array_builder::ArrayBuilder;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use eyre::ensure;
+
+pub use layout_derive::Layout;
+
+/// A type with a fixed-size binary encoding.
+///
+/// Usually implemented with `#[derive(Layout)]`, which keeps the reader,
+/// writer, size and field offsets in sync with a single definition.
+pub trait Layout: Sized {
+    /// Number of bytes in the encoding.
+    const SIZE: usize;
+
+    /// Fields of the encoding in order, for types made up of named fields.
+    const FIELDS: &'static [Field] = &[];
+
+    /// Description of the encoding, used to look up fields by path.
+    const SHAPE: &'static Shape;
+
+    /// Borrowed accessor over encoded bytes.
+    type View<'a>;
+
+    /// Mutable borrowed accessor over encoded bytes.
+    type ViewMut<'a>;
+
+    fn read(reader: &mut impl Read) -> eyre::Result<Self>;
+
+    fn write(&self, writer: &mut impl Write) -> eyre::Result<()>;
+
+    /// Creates a view over `bytes`, which should be `SIZE` bytes long.
+    ///
+    /// Accessing anything that falls outside a shorter slice returns an error
+    /// rather than panicking.
+    fn view(bytes: &[u8]) -> Self::View<'_>;
+
+    /// Creates a mutable view over `bytes`, which should be `SIZE` bytes long.
+    fn view_mut(bytes: &mut [u8]) -> Self::ViewMut<'_>;
+}
+
+/// Position of a named field within its parent's encoding.
+#[derive(Clone, Copy, Debug)]
+pub struct Field {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+    pub shape: &'static Shape,
+    /// Whether the field's own fields can be addressed as if they belonged to
+    /// the parent.
+    pub flatten: bool,
+    pub kind: FieldKind,
+}
+
+/// How well a field's purpose is understood.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldKind {
+    Known,
+    Reserved,
+    Unknown,
+}
+
+#[derive(Debug)]
+pub enum Shape {
+    Value(ValueShape),
+    String,
+    Struct(&'static [Field]),
+    Array {
+        elem: &'static Shape,
+        elem_size: usize,
+        len: usize,
+        /// Names of the elements, which can be used in place of indices.
+        labels: Option<&'static [&'static str]>,
+    },
+}
+
+impl Shape {
+    /// Returns a copy of an array shape with its elements named by `labels`.
+    pub const fn labelled(shape: &'static Shape, labels: &'static [&'static str]) -> Shape {
+        let Shape::Array {
+            elem,
+            elem_size,
+            len,
+            ..
+        } = *shape
+        else {
+            panic!("only arrays can be labelled");
+        };
+        assert!(labels.len() == len, "expected one label per element");
+        Shape::Array {
+            elem,
+            elem_size,
+            len,
+            labels: Some(labels),
+        }
+    }
+}
+
+/// Conversions between the encoding of a single value and text.
+#[derive(Debug)]
+pub struct ValueShape {
+    pub type_name: &'static str,
+    pub format: fn(&[u8]) -> eyre::Result<String>,
+    pub parse: fn(&str) -> eyre::Result<Vec<u8>>,
+}
+
+impl ValueShape {
+    pub const fn of<T>(type_name: &'static str) -> ValueShape
+    where
+        T: Layout + Display + FromStr,
+        T::Err: Into<eyre::Report>,
+    {
+        ValueShape {
+            type_name,
+            format: |bytes| Ok(T::read(&mut &*bytes)?.to_string()),
+            parse: |s| {
+                let value = T::from_str(s.trim()).map_err(Into::into)?;
+                let mut bytes = Vec::with_capacity(T::SIZE);
+                value.write(&mut bytes)?;
+                Ok(bytes)
+            },
+        }
+    }
+}
+
+impl Layout for u8 {
+    const SIZE: usize = 1;
+    const SHAPE: &'static Shape = &Shape::Value(ValueShape::of::<u8>("u8"));
+
+    type View<'a> = Value<'a, u8>;
+    type ViewMut<'a> = ValueMut<'a, u8>;
+
+    fn read(reader: &mut impl Read) -> eyre::Result<u8> {
+        Ok(reader.read_u8()?)
+    }
+
+    fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
+        writer.write_u8(*self)?;
+        Ok(())
+    }
+
+    fn view(bytes: &[u8]) -> Self::View<'_> {
+        Value::new(bytes)
+    }
+
+    fn view_mut(bytes: &mut [u8]) -> Self::ViewMut<'_> {
+        ValueMut::new(bytes)
+    }
+}
+
+impl Layout for u16 {
+    const SIZE: usize = 2;
+    const SHAPE: &'static Shape = &Shape::Value(ValueShape::of::<u16>("u16"));
+
+    type View<'a> = Value<'a, u16>;
+    type ViewMut<'a> = ValueMut<'a, u16>;
+
+    fn read(reader: &mut impl Read) -> eyre::Result<u16> {
+        Ok(reader.read_u16::<BigEndian>()?)
+    }
+
+    fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
+        writer.write_u16::<BigEndian>(*self)?;
+        Ok(())
+    }
+
+    fn view(bytes: &[u8]) -> Self::View<'_> {
+        Value::new(bytes)
+    }
+
+    fn view_mut(bytes: &mut [u8]) -> Self::ViewMut<'_> {
+        ValueMut::new(bytes)
+    }
+}
+
+impl Layout for bool {
+    const SIZE: usize = 1;
+    const SHAPE: &'static Shape = &Shape::Value(ValueShape::of::<bool>("bool"));
+
+    type View<'a> = Value<'a, bool>;
+    type ViewMut<'a> = ValueMut<'a, bool>;
+
+    fn read(reader: &mut impl Read) -> eyre::Result<bool> {
+        Ok(reader.read_u8()? != 0)
+    }
+
+    fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
+        writer.write_u8(*self as u8)?;
+        Ok(())
+    }
+
+    fn view(bytes: &[u8]) -> Self::View<'_> {
+        Value::new(bytes)
+    }
+
+    fn view_mut(bytes: &mut [u8]) -> Self::ViewMut<'_> {
+        ValueMut::new(bytes)
+    }
+}
+
+impl<T: Layout, const N: usize> Layout for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+    const SHAPE: &'static Shape = &Shape::Array {
+        elem: T::SHAPE,
+        elem_size: T::SIZE,
+        len: N,
+        labels: None,
+    };
+
+    type View<'a> = ArrayView<'a, T, N>;
+    type ViewMut<'a> = ArrayViewMut<'a, T, N>;
+
+    fn read(reader: &mut impl Read) -> eyre::Result<[T; N]> {
+        let mut builder = ArrayBuilder::new();
+        for _ in 0..N {
+            builder.push(T::read(reader)?);
+        }
+        Ok(builder.build().unwrap_or_else(|_| unreachable!()))
+    }
+
+    fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
+        for item in self {
+            item.write(writer)?;
+        }
+        Ok(())
+    }
+
+    fn view(bytes: &[u8]) -> Self::View<'_> {
+        ArrayView {
+            bytes,
+            _type: PhantomData,
+        }
+    }
+
+    fn view_mut(bytes: &mut [u8]) -> Self::ViewMut<'_> {
+        ArrayViewMut {
+            bytes,
+            _type: PhantomData,
+        }
+    }
+}
+
+pub fn skip(reader: &mut impl Read, len: usize) -> eyre::Result<()> {
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    Ok(())
+}
+
+pub fn zeros(writer: &mut impl Write, len: usize) -> eyre::Result<()> {
+    writer.write_all(&vec![0; len])?;
+    Ok(())
+}
+
+/// Reads a null-padded string, without its padding. NULs before the last
+/// other byte are kept, and invalid UTF-8 is replaced with U+FFFD.
+pub fn read_string(reader: &mut impl Read, len: usize) -> eyre::Result<String> {
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    let end = buf.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    Ok(String::from_utf8_lossy(&buf[..end]).into_owned())
+}
+
+pub fn write_string(value: &str, writer: &mut impl Write, len: usize) -> eyre::Result<()> {
+    let bytes = value.as_bytes();
+    let mut buf = vec![0; len];
+    let n = bytes.len().min(len);
+    buf[..n].copy_from_slice(&bytes[..n]);
+    writer.write_all(&buf)?;
+    Ok(())
+}
+
+/// Reads an array whose final element is stored without its last `trim`
+/// bytes. The missing bytes are read as zeros.
+pub fn read_trimmed<T: Layout, const N: usize>(
+    reader: &mut impl Read,
+    trim: usize,
+) -> eyre::Result<[T; N]> {
+    let mut buf = vec![0; T::SIZE * N];
+    reader.read_exact(&mut buf[..T::SIZE * N - trim])?;
+    <[T; N]>::read(&mut buf.as_slice())
+}
+
+/// Writes an array, leaving out the last `trim` bytes of its final element.
+pub fn write_trimmed<T: Layout, const N: usize>(
+    value: &[T; N],
+    writer: &mut impl Write,
+    trim: usize,
+) -> eyre::Result<()> {
+    let mut buf = Vec::with_capacity(T::SIZE * N);
+    value.write(&mut buf)?;
+    writer.write_all(&buf[..T::SIZE * N - trim])?;
+    Ok(())
+}
+
+/// Returns the `size` bytes at `offset`, or as many of them as `bytes`
+/// contains.
+pub fn sub(bytes: &[u8], offset: usize, size: usize) -> &[u8] {
+    let bytes = bytes.get(offset..).unwrap_or_default();
+    &bytes[..size.min(bytes.len())]
+}
+
+/// Mutable version of [`sub`].
+pub fn sub_mut(bytes: &mut [u8], offset: usize, size: usize) -> &mut [u8] {
+    let bytes = bytes.get_mut(offset..).unwrap_or_default();
+    let len = size.min(bytes.len());
+    &mut bytes[..len]
+}
+
+/// View of a single value.
+pub struct Value<'a, T> {
+    bytes: &'a [u8],
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: Layout> Value<'a, T> {
+    pub fn new(bytes: &'a [u8]) -> Value<'a, T> {
+        Value {
+            bytes,
+            _type: PhantomData,
+        }
+    }
+
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn get(&self) -> eyre::Result<T> {
+        T::read(&mut &*self.bytes)
+    }
+}
+
+/// Mutable view of a single value.
+pub struct ValueMut<'a, T> {
+    bytes: &'a mut [u8],
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: Layout> ValueMut<'a, T> {
+    pub fn new(bytes: &'a mut [u8]) -> ValueMut<'a, T> {
+        ValueMut {
+            bytes,
+            _type: PhantomData,
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        self.bytes
+    }
+
+    pub fn get(&self) -> eyre::Result<T> {
+        T::read(&mut &*self.bytes)
+    }
+
+    pub fn set(&mut self, value: &T) -> eyre::Result<()> {
+        set_bytes(self.bytes, T::SIZE, |writer| value.write(writer))
+    }
+}
+
+/// Encodes a value into `bytes` if it is exactly `size` bytes long, leaving
+/// it untouched otherwise.
+pub fn set_bytes(
+    bytes: &mut [u8],
+    size: usize,
+    write: impl FnOnce(&mut Vec<u8>) -> eyre::Result<()>,
+) -> eyre::Result<()> {
+    ensure!(
+        bytes.len() == size,
+        "value needs {size} bytes but only {} are stored",
+        bytes.len()
+    );
+    let mut buf = Vec::with_capacity(size);
+    write(&mut buf)?;
+    bytes.copy_from_slice(&buf);
+    Ok(())
+}
+
+/// View of a fixed-length, null-padded string.
+pub struct Str<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Str<'a> {
+    pub fn new(bytes: &'a [u8]) -> Str<'a> {
+        Str { bytes }
+    }
+
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn get(&self) -> eyre::Result<String> {
+        read_string(&mut &*self.bytes, self.bytes.len())
+    }
+}
+
+/// Mutable view of a fixed-length, null-padded string.
+pub struct StrMut<'a> {
+    bytes: &'a mut [u8],
+}
+
+impl<'a> StrMut<'a> {
+    pub fn new(bytes: &'a mut [u8]) -> StrMut<'a> {
+        StrMut { bytes }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        self.bytes
+    }
+
+    pub fn get(&self) -> eyre::Result<String> {
+        read_string(&mut &*self.bytes, self.bytes.len())
+    }
+
+    pub fn set(&mut self, value: &str) -> eyre::Result<()> {
+        let len = self.bytes.len();
+        write_string(value, &mut &mut *self.bytes, len)
+    }
+}
+
+/// View of an array of values.
+pub struct ArrayView<'a, T, const N: usize> {
+    bytes: &'a [u8],
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: Layout, const N: usize> ArrayView<'a, T, N> {
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        N
+    }
+
+    pub fn is_empty(&self) -> bool {
+        N == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<T::View<'a>> {
+        (index < N).then(|| T::view(sub(self.bytes, index * T::SIZE, T::SIZE)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T::View<'a>> {
+        let bytes = self.bytes;
+        (0..N).map(move |i| T::view(sub(bytes, i * T::SIZE, T::SIZE)))
+    }
+}
+
+/// Mutable view of an array of values.
+pub struct ArrayViewMut<'a, T, const N: usize> {
+    bytes: &'a mut [u8],
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: Layout, const N: usize> ArrayViewMut<'a, T, N> {
+    pub fn bytes(&self) -> &[u8] {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        N
+    }
+
+    pub fn is_empty(&self) -> bool {
+        N == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<T::View<'_>> {
+        (index < N).then(|| T::view(sub(self.bytes, index * T::SIZE, T::SIZE)))
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<T::ViewMut<'_>> {
+        (index < N).then(|| T::view_mut(sub_mut(self.bytes, index * T::SIZE, T::SIZE)))
+    }
+}
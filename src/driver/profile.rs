@@ -1,10 +1,14 @@
-use std::io::{Read, Seek, Write};
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::FromStr;
 
-use array_builder::ArrayBuilder;
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use eyre::bail;
+use eyre::{bail, ensure, eyre};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+use crate::driver::KeyCode;
+use crate::driver::layout::{Layout, Shape, Value, ValueMut, ValueShape};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ProfileId {
     Num(ProfileNum),
     Shift,
@@ -12,6 +16,24 @@ pub enum ProfileId {
 }
 
 impl ProfileId {
+    /// Every profile stored on the device.
+    pub const ALL: [ProfileId; 6] = [
+        ProfileId::Num(ProfileNum::P1),
+        ProfileId::Num(ProfileNum::P2),
+        ProfileId::Num(ProfileNum::P3),
+        ProfileId::Num(ProfileNum::P4),
+        ProfileId::Shift,
+        ProfileId::Light,
+    ];
+
+    pub fn from_index(index: u8) -> Option<ProfileId> {
+        match index {
+            5 => Some(ProfileId::Shift),
+            32 => Some(ProfileId::Light),
+            _ => ProfileNum::from_index(index).map(ProfileId::Num),
+        }
+    }
+
     pub fn index(&self) -> u8 {
         match self {
             ProfileId::Num(profile_num) => profile_num.index(),
@@ -19,9 +41,112 @@ impl ProfileId {
             ProfileId::Light => 32,
         }
     }
+
+    pub fn kind(&self) -> ProfileKind {
+        match self {
+            ProfileId::Num(_) | ProfileId::Shift => ProfileKind::Control,
+            ProfileId::Light => ProfileKind::Light,
+        }
+    }
+}
+
+impl fmt::Display for ProfileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileId::Num(num) => write!(f, "{}", num.index()),
+            ProfileId::Shift => f.write_str("shift"),
+            ProfileId::Light => f.write_str("light"),
+        }
+    }
 }
 
+impl FromStr for ProfileId {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<ProfileId> {
+        Ok(match s {
+            "shift" => ProfileId::Shift,
+            "light" => ProfileId::Light,
+            _ => ProfileId::Num(s.parse()?),
+        })
+    }
+}
+
+impl Serialize for ProfileId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProfileId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ProfileId, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The two kinds of profile stored on the device.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileKind {
+    Control,
+    Light,
+}
+
+impl ProfileKind {
+    /// Size of the profile's encoding.
+    pub fn size(&self) -> usize {
+        match self {
+            ProfileKind::Control => ControlProfile::SIZE,
+            ProfileKind::Light => LightProfile::SIZE,
+        }
+    }
+
+    pub fn shape(&self) -> &'static Shape {
+        match self {
+            ProfileKind::Control => ControlProfile::SHAPE,
+            ProfileKind::Light => LightProfile::SHAPE,
+        }
+    }
+
+    /// Returns the kind of profile whose encoding is `size` bytes long.
+    pub fn from_size(size: usize) -> Option<ProfileKind> {
+        match size {
+            ControlProfile::SIZE => Some(ProfileKind::Control),
+            LightProfile::SIZE => Some(ProfileKind::Light),
+            _ => None,
+        }
+    }
+
+    /// Checks that `bytes` decode to a valid profile of this kind.
+    pub fn validate(&self, bytes: &[u8]) -> eyre::Result<()> {
+        ensure!(
+            bytes.len() == self.size(),
+            "expected {} bytes but got {}",
+            self.size(),
+            bytes.len()
+        );
+        match self {
+            ProfileKind::Control => {
+                ControlProfile::read(&mut &*bytes)?;
+            }
+            ProfileKind::Light => {
+                LightProfile::read(&mut &*bytes)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ProfileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProfileKind::Control => "control",
+            ProfileKind::Light => "light",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ProfileNum {
     P1,
     P2,
@@ -38,24 +163,32 @@ impl ProfileNum {
             ProfileNum::P4 => 4,
         }
     }
-}
 
-macro_rules! array_of {
-    (|| $e:expr) => {array_of!(|_| $e)};
-    (|$i:tt| $e:expr) => {{
-        let mut builder = ArrayBuilder::new();
-        fn len<T, const N: usize>(_: &ArrayBuilder<T, N>) -> usize {
-            N
+    pub fn from_index(index: u8) -> Option<ProfileNum> {
+        match index {
+            1 => Some(ProfileNum::P1),
+            2 => Some(ProfileNum::P2),
+            3 => Some(ProfileNum::P3),
+            4 => Some(ProfileNum::P4),
+            _ => None,
         }
-        for $i in 0..len(&builder) {
-            builder.push($e);
-        }
-        builder.build().unwrap()
-    }};
+    }
 }
 
-#[derive(Debug)]
+impl FromStr for ProfileNum {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<ProfileNum> {
+        s.parse()
+            .ok()
+            .and_then(ProfileNum::from_index)
+            .ok_or_else(|| eyre!("invalid profile: {s}"))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Layout)]
 pub struct ControlProfile {
+    #[layout(string = 32)]
     pub name: String,
     // Fun_Data
     pub left_motor_value: u8,
@@ -73,7 +206,9 @@ pub struct ControlProfile {
     pub xinput_abxy_change: u8,
     pub switch_abxy_change: u8,
     pub report_rates_gears: u8,
+    #[layout(pad_before = 17, labels = &BUTTON_NAMES)]
     pub mappings: [ButtonMapping; 16],
+    #[layout(labels = &["FL1", "FR1"])]
     pub fn_mappings: [FunctionKeyConfig; 2],
     pub left_trigger: TriggerConfig,
     pub right_trigger: TriggerConfig,
@@ -83,462 +218,351 @@ pub struct ControlProfile {
     pub tilt_sensor: MotionConfig,
 }
 
+const _: () = assert!(ControlProfile::SIZE == 680);
+
 impl ControlProfile {
-    pub fn read(reader: &mut (impl Read + Seek)) -> eyre::Result<ControlProfile> {
-        Ok(ControlProfile {
-            name: {
-                let mut buf = [0; 32];
-                reader.read_exact(&mut buf)?;
-                String::from_utf8_lossy(&buf).into_owned()
-            },
-            left_motor_value: reader.read_u8()?,
-            right_motor_value: reader.read_u8()?,
-            lt_motor_value: reader.read_u8()?,
-            rt_motor_value: reader.read_u8()?,
-            profile_audio_en: reader.read_u8()?,
-            audio_volume: reader.read_u8()?,
-            audio_mixer: reader.read_u8()?,
-            mic_mute: reader.read_u8()?,
-            mic_sensitivity: reader.read_u8()?,
-            shift_en: reader.read_u8()?,
-            shift_value: reader.read_u8()?,
-            dpad_diagonal_lock_en: reader.read_u8()?,
-            xinput_abxy_change: reader.read_u8()?,
-            switch_abxy_change: reader.read_u8()?,
-            report_rates_gears: reader.read_u8()?,
-            mappings: {
-                // Skip 17 reserved bytes
-                reader.seek_relative(17)?;
-                array_of!(|| ButtonMapping::read(reader)?)
-            },
-            fn_mappings: array_of!(|| FunctionKeyConfig::read(reader)?),
-            left_trigger: TriggerConfig::read(reader)?,
-            right_trigger: TriggerConfig::read(reader)?,
-            left_stick: StickConfig::read(reader)?,
-            right_stick: StickConfig::read(reader)?,
-            aim_sensor: MotionConfig::read(reader)?,
-            tilt_sensor: MotionConfig::read(reader)?,
-        })
+    /// Longest name, in bytes. The field is 32 bytes, but the app always
+    /// leaves a NUL at the end.
+    pub const MAX_NAME_LEN: usize = 31;
+}
+
+/// Buttons in the order of `ControlProfile::mappings`.
+pub const BUTTON_NAMES: [&str; 16] = [
+    "Up", "Down", "Left", "Right", "L1", "R1", "L3", "R3", "Cross", "Circle", "Square", "Triangle",
+    "Home", "Select", "Start", "Capture",
+];
+
+impl<'a> From<&'a [u8; ControlProfile::SIZE]> for ControlProfileView<'a> {
+    fn from(bytes: &'a [u8; ControlProfile::SIZE]) -> ControlProfileView<'a> {
+        ControlProfile::view(bytes)
     }
+}
 
-    pub fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
-        let name_bytes = self.name.as_bytes();
-        let mut name_buf = [0u8; 32];
-        let len = name_bytes.len().min(32);
-        name_buf[..len].copy_from_slice(&name_bytes[..len]);
-        writer.write_all(&name_buf)?;
-        writer.write_u8(self.left_motor_value)?;
-        writer.write_u8(self.right_motor_value)?;
-        writer.write_u8(self.lt_motor_value)?;
-        writer.write_u8(self.rt_motor_value)?;
-        writer.write_u8(self.profile_audio_en)?;
-        writer.write_u8(self.audio_volume)?;
-        writer.write_u8(self.audio_mixer)?;
-        writer.write_u8(self.mic_mute)?;
-        writer.write_u8(self.mic_sensitivity)?;
-        writer.write_u8(self.shift_en)?;
-        writer.write_u8(self.shift_value)?;
-        writer.write_u8(self.dpad_diagonal_lock_en)?;
-        writer.write_u8(self.xinput_abxy_change)?;
-        writer.write_u8(self.switch_abxy_change)?;
-        writer.write_u8(self.report_rates_gears)?;
-        writer.write_all(&[0u8; 17])?;
-        for mapping in &self.mappings {
-            mapping.write(writer)?;
-        }
-        for fn_mapping in &self.fn_mappings {
-            fn_mapping.write(writer)?;
-        }
-        self.left_trigger.write(writer)?;
-        self.right_trigger.write(writer)?;
-        self.left_stick.write(writer)?;
-        self.right_stick.write(writer)?;
-        self.aim_sensor.write(writer)?;
-        self.tilt_sensor.write(writer)?;
-        Ok(())
+impl<'a> From<&'a mut [u8; ControlProfile::SIZE]> for ControlProfileViewMut<'a> {
+    fn from(bytes: &'a mut [u8; ControlProfile::SIZE]) -> ControlProfileViewMut<'a> {
+        ControlProfile::view_mut(bytes)
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Layout)]
 pub struct ButtonMapping {
-    pub turbo_en: u8,
-    pub turbo_speed: u8,
+    #[layout(flatten)]
+    pub turbo_module: TurboModule,
     pub map_en: u8,
-    pub map: [u8; 3],
+    pub map: [KeyCode; 3],
     pub toggle_en: u8,
 }
 
-impl ButtonMapping {
-    pub fn read(reader: &mut impl Read) -> eyre::Result<ButtonMapping> {
-        Ok(ButtonMapping {
-            turbo_en: reader.read_u8()?,
-            turbo_speed: reader.read_u8()?,
-            map_en: reader.read_u8()?,
-            map: array_of!(|| reader.read_u8()?),
-            toggle_en: reader.read_u8()?,
-        })
-    }
-
-    pub fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
-        writer.write_u8(self.turbo_en)?;
-        writer.write_u8(self.turbo_speed)?;
-        writer.write_u8(self.map_en)?;
-        writer.write_all(&self.map)?;
-        writer.write_u8(self.toggle_en)?;
-        Ok(())
-    }
-}
-
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Layout)]
 pub struct FunctionKeyConfig {
+    #[layout(flatten)]
     pub mapping: ButtonMapping,
     pub macro_open_status: u8,
     pub macro_cycle_time: u16,
     pub step_num: u8,
+    // The final step has no delay
+    #[layout(trim_last = 2)]
     pub steps: [MacroStep; 30],
 }
 
-impl FunctionKeyConfig {
-    pub fn read(reader: &mut impl Read) -> eyre::Result<FunctionKeyConfig> {
-        Ok(FunctionKeyConfig {
-            mapping: ButtonMapping::read(reader)?,
-            macro_open_status: reader.read_u8()?,
-            macro_cycle_time: reader.read_u16::<BigEndian>()?,
-            step_num: reader.read_u8()?,
-            steps: array_of!(|i| MacroStep {
-                step_data: reader.read_u8()?,
-                step_hold_time: reader.read_u16::<BigEndian>()?,
-                step_delay_time: if i < 29 {
-                    reader.read_u16::<BigEndian>()?
-                } else {
-                    0
-                },
-            }),
-        })
-    }
-
-    pub fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
-        self.mapping.write(writer)?;
-        writer.write_u8(self.macro_open_status)?;
-        writer.write_u16::<BigEndian>(self.macro_cycle_time)?;
-        writer.write_u8(self.step_num)?;
-        for (i, step) in self.steps.iter().enumerate() {
-            writer.write_u8(step.step_data)?;
-            writer.write_u16::<BigEndian>(step.step_hold_time)?;
-            if i < 29 {
-                writer.write_u16::<BigEndian>(step.step_delay_time)?;
-            }
-        }
-        Ok(())
-    }
-}
-
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Layout)]
 pub struct MacroStep {
-    pub step_data: u8,
+    pub step_data: KeyCode,
     pub step_hold_time: u16,
     // Not set for the final step
     pub step_delay_time: u16,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Layout)]
 pub struct TriggerConfig {
-    // turbo_module
-    pub turbo_en: u8,
-    pub turbo_speed: u8,
-    // dead_module
-    pub dead_en: u8,
-    pub front_dead: u8,
-    pub back_dead: u8,
-    pub anti_front_dead: u8,
-    pub anti_back_dead: u8,
+    #[layout(flatten)]
+    pub turbo_module: TurboModule,
+    #[layout(flatten)]
+    pub dead_module: DeadzoneModule,
     pub map_en: u8,
-    pub map: [u8; 3],
+    pub map: [KeyCode; 3],
     pub toggle_en: u8,
     // quick_trigger
     pub quick_trigger_status: u8,
     pub quick_trigger_start_value: u8,
     pub quick_trigger_end_value: u8,
-    // linear_module
-    pub linear_module_en: u8,
-    pub linear_status: u8,
-    pub linear_data: u8,
-    pub linear_control_points: [(u8, u8); 5],
-}
-
-impl TriggerConfig {
-    pub fn read(reader: &mut impl Read) -> eyre::Result<TriggerConfig> {
-        Ok(TriggerConfig {
-            turbo_en: reader.read_u8()?,
-            turbo_speed: reader.read_u8()?,
-            dead_en: reader.read_u8()?,
-            front_dead: reader.read_u8()?,
-            back_dead: reader.read_u8()?,
-            anti_front_dead: reader.read_u8()?,
-            anti_back_dead: reader.read_u8()?,
-            map_en: reader.read_u8()?,
-            map: array_of!(|| reader.read_u8()?),
-            toggle_en: reader.read_u8()?,
-            quick_trigger_status: reader.read_u8()?,
-            quick_trigger_start_value: reader.read_u8()?,
-            quick_trigger_end_value: reader.read_u8()?,
-            linear_module_en: reader.read_u8()?,
-            linear_status: reader.read_u8()?,
-            linear_data: reader.read_u8()?,
-            linear_control_points: array_of!(|| (reader.read_u8()?, reader.read_u8()?)),
-        })
-    }
-
-    pub fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
-        writer.write_u8(self.turbo_en)?;
-        writer.write_u8(self.turbo_speed)?;
-        writer.write_u8(self.dead_en)?;
-        writer.write_u8(self.front_dead)?;
-        writer.write_u8(self.back_dead)?;
-        writer.write_u8(self.anti_front_dead)?;
-        writer.write_u8(self.anti_back_dead)?;
-        writer.write_u8(self.map_en)?;
-        writer.write_all(&self.map)?;
-        writer.write_u8(self.toggle_en)?;
-        writer.write_u8(self.quick_trigger_status)?;
-        writer.write_u8(self.quick_trigger_start_value)?;
-        writer.write_u8(self.quick_trigger_end_value)?;
-        writer.write_u8(self.linear_module_en)?;
-        writer.write_u8(self.linear_status)?;
-        writer.write_u8(self.linear_data)?;
-        for (a, b) in &self.linear_control_points {
-            writer.write_u8(*a)?;
-            writer.write_u8(*b)?;
-        }
-        Ok(())
-    }
+    #[layout(flatten)]
+    pub linear_module: ResponseCurve,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Layout)]
 pub struct StickConfig {
     pub stick_en: u8,
     pub stick_square: u8,
-    pub dead_en: u8,
-    pub front_dead: u8,
-    pub back_dead: u8,
-    pub anti_front_dead: u8,
-    pub anti_back_dead: u8,
-    pub linear_module_en: u8,
-    pub linear_status: u8,
-    pub linear_data: u8,
-    pub linear_control_points: [(u8, u8); 5],
-    pub map_en: u8,
-    pub x_flip: u8,
-    pub y_flip: u8,
-    pub axis_ratio: u8,
-    pub mouse_dpi: u8,
-    pub map_index: u8,
-    pub map_cross: u8,
-    pub map_up_value: u8,
-    pub map_down_value: u8,
-    pub map_left_value: u8,
-    pub map_right_value: u8,
-    pub map_dead_value: u8,
-}
-
-impl StickConfig {
-    pub fn read(reader: &mut impl Read) -> eyre::Result<StickConfig> {
-        Ok(StickConfig {
-            stick_en: reader.read_u8()?,
-            stick_square: reader.read_u8()?,
-            dead_en: reader.read_u8()?,
-            front_dead: reader.read_u8()?,
-            back_dead: reader.read_u8()?,
-            anti_front_dead: reader.read_u8()?,
-            anti_back_dead: reader.read_u8()?,
-            linear_module_en: reader.read_u8()?,
-            linear_status: reader.read_u8()?,
-            linear_data: reader.read_u8()?,
-            linear_control_points: array_of!(|| (reader.read_u8()?, reader.read_u8()?)),
-            map_en: reader.read_u8()?,
-            x_flip: reader.read_u8()?,
-            y_flip: reader.read_u8()?,
-            axis_ratio: reader.read_u8()?,
-            mouse_dpi: reader.read_u8()?,
-            map_index: reader.read_u8()?,
-            map_cross: reader.read_u8()?,
-            map_up_value: reader.read_u8()?,
-            map_down_value: reader.read_u8()?,
-            map_left_value: reader.read_u8()?,
-            map_right_value: reader.read_u8()?,
-            map_dead_value: reader.read_u8()?,
-        })
+    #[layout(flatten)]
+    pub dead_module: DeadzoneModule,
+    #[layout(flatten)]
+    pub linear_module: ResponseCurve,
+    #[layout(flatten)]
+    pub map_module: AxisMapModule,
+}
+
+#[derive(Clone, Debug, PartialEq, Layout)]
+pub struct MotionConfig {
+    pub sensor_profile_status: SensorActivation,
+    pub sensor_quick_key_value: KeyCode,
+    pub active_axis: u8,
+    #[layout(flatten)]
+    pub dead_module: DeadzoneModule,
+    #[layout(flatten)]
+    pub linear_module: ResponseCurve,
+    #[layout(flatten)]
+    pub map_module: AxisMapModule,
+}
+
+/// A percentage, which should be between 0 and 100. Bytes read from a
+/// controller are kept even when out of range, so that they can be written
+/// back unchanged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Percent(u8);
+
+impl Percent {
+    pub fn new(value: u8) -> Option<Percent> {
+        (value <= 100).then_some(Percent(value))
+    }
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.0)
+    }
+}
+
+impl FromStr for Percent {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Percent> {
+        let value = s.strip_suffix('%').unwrap_or(s).trim().parse()?;
+        Percent::new(value).ok_or_else(|| eyre!("percentage must be between 0 and 100: {value}"))
+    }
+}
+
+impl Layout for Percent {
+    const SIZE: usize = 1;
+    const SHAPE: &'static Shape = &Shape::Value(ValueShape::of::<Percent>("Percent"));
+
+    type View<'a> = Value<'a, Percent>;
+    type ViewMut<'a> = ValueMut<'a, Percent>;
+
+    fn read(reader: &mut impl Read) -> eyre::Result<Percent> {
+        Ok(Percent(u8::read(reader)?))
+    }
+
+    fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
+        self.0.write(writer)
+    }
+
+    fn view(bytes: &[u8]) -> Self::View<'_> {
+        Value::new(bytes)
+    }
+
+    fn view_mut(bytes: &mut [u8]) -> Self::ViewMut<'_> {
+        ValueMut::new(bytes)
+    }
+}
+
+/// An on/off setting, which is on when bit 0 of its byte is set. The whole
+/// byte is kept so that a value read from a controller is written back
+/// unchanged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Flag(pub u8);
+
+impl Flag {
+    pub const OFF: Flag = Flag(0);
+    pub const ON: Flag = Flag(1);
+
+    pub fn is_on(self) -> bool {
+        self.0 & 1 != 0
     }
+}
 
-    pub fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
-        writer.write_u8(self.stick_en)?;
-        writer.write_u8(self.stick_square)?;
-        writer.write_u8(self.dead_en)?;
-        writer.write_u8(self.front_dead)?;
-        writer.write_u8(self.back_dead)?;
-        writer.write_u8(self.anti_front_dead)?;
-        writer.write_u8(self.anti_back_dead)?;
-        writer.write_u8(self.linear_module_en)?;
-        writer.write_u8(self.linear_status)?;
-        writer.write_u8(self.linear_data)?;
-        for (a, b) in &self.linear_control_points {
-            writer.write_u8(*a)?;
-            writer.write_u8(*b)?;
+impl From<bool> for Flag {
+    fn from(value: bool) -> Flag {
+        Flag(value.into())
+    }
+}
+
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            0 => f.write_str("false"),
+            1 => f.write_str("true"),
+            other => write!(f, "{} ({other})", self.is_on()),
         }
-        writer.write_u8(self.map_en)?;
-        writer.write_u8(self.x_flip)?;
-        writer.write_u8(self.y_flip)?;
-        writer.write_u8(self.axis_ratio)?;
-        writer.write_u8(self.mouse_dpi)?;
-        writer.write_u8(self.map_index)?;
-        writer.write_u8(self.map_cross)?;
-        writer.write_u8(self.map_up_value)?;
-        writer.write_u8(self.map_down_value)?;
-        writer.write_u8(self.map_left_value)?;
-        writer.write_u8(self.map_right_value)?;
-        writer.write_u8(self.map_dead_value)?;
-        Ok(())
     }
 }
 
-#[derive(Debug)]
-pub struct MotionConfig {
-    pub sensor_profile_status: u8,
-    pub sensor_quick_key_value: u8,
-    pub active_axis: u8,
-    pub dead_en: u8,
-    pub front_dead: u8,
-    pub back_dead: u8,
-    pub anti_front_dead: u8,
-    pub anti_back_dead: u8,
-    pub linear_module_en: u8,
+impl FromStr for Flag {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Flag> {
+        match s {
+            "true" | "1" => Ok(Flag::ON),
+            "false" | "0" => Ok(Flag::OFF),
+            _ => bail!("expected true or false: {s}"),
+        }
+    }
+}
+
+impl Layout for Flag {
+    const SIZE: usize = 1;
+    const SHAPE: &'static Shape = &Shape::Value(ValueShape::of::<Flag>("Flag"));
+
+    type View<'a> = Value<'a, Flag>;
+    type ViewMut<'a> = ValueMut<'a, Flag>;
+
+    fn read(reader: &mut impl Read) -> eyre::Result<Flag> {
+        Ok(Flag(u8::read(reader)?))
+    }
+
+    fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
+        self.0.write(writer)
+    }
+
+    fn view(bytes: &[u8]) -> Self::View<'_> {
+        Value::new(bytes)
+    }
+
+    fn view_mut(bytes: &mut [u8]) -> Self::ViewMut<'_> {
+        ValueMut::new(bytes)
+    }
+}
+
+// turbo_module
+#[derive(Clone, Debug, PartialEq, Layout)]
+pub struct TurboModule {
+    pub turbo_en: Flag,
+    pub turbo_speed: u8,
+}
+
+// dead_module
+#[derive(Clone, Debug, PartialEq, Layout)]
+pub struct DeadzoneModule {
+    pub dead_en: Flag,
+    pub front_dead: Percent,
+    pub back_dead: Percent,
+    pub anti_front_dead: Percent,
+    pub anti_back_dead: Percent,
+}
+
+// linear_module
+#[derive(Clone, Debug, PartialEq, Layout)]
+pub struct ResponseCurve {
+    pub linear_module_en: Flag,
     pub linear_status: u8,
     pub linear_data: u8,
-    pub linear_control_points: [(u8, u8); 5],
-    pub map_en: u8,
-    pub x_flip: u8,
-    pub y_flip: u8,
-    pub axis_ratio: u8,
+    pub linear_control_points: [CurvePoint; 5],
+}
+
+#[derive(Clone, Debug, PartialEq, Layout)]
+pub struct CurvePoint {
+    pub original_data: u8,
+    pub target_data: u8,
+}
+
+// map_module for sticks and motion sensors
+#[derive(Clone, Debug, PartialEq, Layout)]
+pub struct AxisMapModule {
+    pub map_en: Flag,
+    pub x_flip: Flag,
+    pub y_flip: Flag,
+    pub axis_ratio: Percent,
     pub mouse_dpi: u8,
-    pub map_index: u8,
+    pub map_index: AxisMapTarget,
     pub map_cross: u8,
-    pub map_up_value: u8,
-    pub map_down_value: u8,
-    pub map_left_value: u8,
-    pub map_right_value: u8,
-    pub map_dead_value: u8,
-}
-
-impl MotionConfig {
-    pub fn read(reader: &mut impl Read) -> eyre::Result<MotionConfig> {
-        Ok(MotionConfig {
-            sensor_profile_status: reader.read_u8()?,
-            sensor_quick_key_value: reader.read_u8()?,
-            active_axis: reader.read_u8()?,
-            dead_en: reader.read_u8()?,
-            front_dead: reader.read_u8()?,
-            back_dead: reader.read_u8()?,
-            anti_front_dead: reader.read_u8()?,
-            anti_back_dead: reader.read_u8()?,
-            linear_module_en: reader.read_u8()?,
-            linear_status: reader.read_u8()?,
-            linear_data: reader.read_u8()?,
-            linear_control_points: array_of!(|| (reader.read_u8()?, reader.read_u8()?)),
-            map_en: reader.read_u8()?,
-            x_flip: reader.read_u8()?,
-            y_flip: reader.read_u8()?,
-            axis_ratio: reader.read_u8()?,
-            mouse_dpi: reader.read_u8()?,
-            map_index: reader.read_u8()?,
-            map_cross: reader.read_u8()?,
-            map_up_value: reader.read_u8()?,
-            map_down_value: reader.read_u8()?,
-            map_left_value: reader.read_u8()?,
-            map_right_value: reader.read_u8()?,
-            map_dead_value: reader.read_u8()?,
-        })
+    pub map_up_value: KeyCode,
+    pub map_down_value: KeyCode,
+    pub map_left_value: KeyCode,
+    pub map_right_value: KeyCode,
+    pub map_dead_value: KeyCode,
+}
+
+/// Output that a stick or motion sensor is mapped to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Layout)]
+#[repr(u8)]
+pub enum AxisMapTarget {
+    LeftStick = 1,
+    RightStick = 2,
+    Wheel = 3,
+    Mouse = 4,
+    #[layout(other)]
+    Unknown(u8),
+}
+
+impl AxisMapTarget {
+    pub fn index(&self) -> u8 {
+        u8::from(*self)
     }
+}
 
-    pub fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
-        writer.write_u8(self.sensor_profile_status)?;
-        writer.write_u8(self.sensor_quick_key_value)?;
-        writer.write_u8(self.active_axis)?;
-        writer.write_u8(self.dead_en)?;
-        writer.write_u8(self.front_dead)?;
-        writer.write_u8(self.back_dead)?;
-        writer.write_u8(self.anti_front_dead)?;
-        writer.write_u8(self.anti_back_dead)?;
-        writer.write_u8(self.linear_module_en)?;
-        writer.write_u8(self.linear_status)?;
-        writer.write_u8(self.linear_data)?;
-        for (a, b) in &self.linear_control_points {
-            writer.write_u8(*a)?;
-            writer.write_u8(*b)?;
-        }
-        writer.write_u8(self.map_en)?;
-        writer.write_u8(self.x_flip)?;
-        writer.write_u8(self.y_flip)?;
-        writer.write_u8(self.axis_ratio)?;
-        writer.write_u8(self.mouse_dpi)?;
-        writer.write_u8(self.map_index)?;
-        writer.write_u8(self.map_cross)?;
-        writer.write_u8(self.map_up_value)?;
-        writer.write_u8(self.map_down_value)?;
-        writer.write_u8(self.map_left_value)?;
-        writer.write_u8(self.map_right_value)?;
-        writer.write_u8(self.map_dead_value)?;
-        Ok(())
+/// How a motion sensor is activated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Layout)]
+#[repr(u8)]
+pub enum SensorActivation {
+    Off = 0,
+    AlwaysOn = 1,
+    Hold = 2,
+    #[layout(other)]
+    Unknown(u8),
+}
+
+impl SensorActivation {
+    pub fn index(&self) -> u8 {
+        u8::from(*self)
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Layout)]
+#[layout(validate = LightProfile::validate)]
 pub struct LightProfile {
     pub config_index: u8,
     pub animations: [Animation; 5],
-    pub audio_reactive_mode: bool,
+    pub audio_reactive_mode: Flag,
+    #[layout(unknown)]
     pub user_effect_index: u8, // Doesn't appear to be used for anything
     pub profile_led: RgbColor,
-    pub raise_wake_up: bool,
+    pub raise_wake_up: Flag,
     pub standby_time: u8,
+    #[layout(reserved)]
     pub reserved_data: [u8; 7],
 }
 
-impl LightProfile {
-    pub fn read(reader: &mut impl Read) -> eyre::Result<LightProfile> {
-        let config_index = reader.read_u8()?;
+const _: () = assert!(LightProfile::SIZE == 635);
 
-        if config_index > 3 {
-            bail!("config index must be between 0 and 3: {config_index}");
-        }
+impl<'a> From<&'a [u8; LightProfile::SIZE]> for LightProfileView<'a> {
+    fn from(bytes: &'a [u8; LightProfile::SIZE]) -> LightProfileView<'a> {
+        LightProfile::view(bytes)
+    }
+}
 
-        Ok(LightProfile {
-            config_index,
-            animations: array_of!(|| Animation::read(reader)?),
-            audio_reactive_mode: reader.read_u8()? == 1,
-            user_effect_index: reader.read_u8()?,
-            profile_led: RgbColor::read(reader)?,
-            raise_wake_up: reader.read_u8()? == 1,
-            standby_time: reader.read_u8()?,
-            reserved_data: array_of!(|| reader.read_u8()?),
-        })
+impl<'a> From<&'a mut [u8; LightProfile::SIZE]> for LightProfileViewMut<'a> {
+    fn from(bytes: &'a mut [u8; LightProfile::SIZE]) -> LightProfileViewMut<'a> {
+        LightProfile::view_mut(bytes)
     }
+}
 
-    pub fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
-        writer.write_u8(self.config_index)?;
-        for animation in &self.animations {
-            animation.write(writer)?;
+impl LightProfile {
+    fn validate(&self) -> eyre::Result<()> {
+        if self.config_index > 3 {
+            bail!(
+                "config index must be between 0 and 3: {}",
+                self.config_index
+            );
         }
-        writer.write_u8(self.audio_reactive_mode as u8)?;
-        writer.write_u8(self.user_effect_index)?;
-        self.profile_led.write(writer)?;
-        writer.write_u8(self.raise_wake_up as u8)?;
-        writer.write_u8(self.standby_time)?;
-        writer.write_all(&self.reserved_data)?;
         Ok(())
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Layout)]
 pub struct Animation {
     pub key_frame_count: u8,
     pub effect_count: u8,
@@ -547,69 +571,14 @@ pub struct Animation {
     pub frames: [Frame; 8],
 }
 
-impl Animation {
-    pub fn read(reader: &mut impl Read) -> eyre::Result<Animation> {
-        Ok(Animation {
-            key_frame_count: reader.read_u8()?,
-            effect_count: reader.read_u8()?,
-            speed: reader.read_u8()?,
-            brightness: reader.read_u8()?,
-            frames: array_of!(|| Frame::read(reader)?),
-        })
-    }
-
-    pub fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
-        writer.write_u8(self.key_frame_count)?;
-        writer.write_u8(self.effect_count)?;
-        writer.write_u8(self.speed)?;
-        writer.write_u8(self.brightness)?;
-        for frame in &self.frames {
-            frame.write(writer)?;
-        }
-        Ok(())
-    }
-}
-
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Layout)]
 pub struct Frame {
     pub leds: [RgbColor; 5],
 }
 
-impl Frame {
-    pub fn read(reader: &mut impl Read) -> eyre::Result<Frame> {
-        Ok(Frame {
-            leds: array_of!(|| RgbColor::read(reader)?),
-        })
-    }
-
-    pub fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
-        for led in &self.leds {
-            led.write(writer)?;
-        }
-        Ok(())
-    }
-}
-
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Layout)]
 pub struct RgbColor {
     pub red: u8,
     pub green: u8,
     pub blue: u8,
 }
-
-impl RgbColor {
-    pub fn read(reader: &mut impl Read) -> eyre::Result<RgbColor> {
-        Ok(RgbColor {
-            red: reader.read_u8()?,
-            green: reader.read_u8()?,
-            blue: reader.read_u8()?,
-        })
-    }
-
-    pub fn write(&self, writer: &mut impl Write) -> eyre::Result<()> {
-        writer.write_u8(self.red)?;
-        writer.write_u8(self.green)?;
-        writer.write_u8(self.blue)?;
-        Ok(())
-    }
-}
@@ -0,0 +1,322 @@
+//! Constants and descriptions of the configuration protocol, as documented
+//! in `C2_PROTOCOL.md`.
+
+use crate::driver::ProfileId;
+
+pub const REPORT_SIZE: usize = 64;
+
+/// Largest number of profile bytes carried by one read or write.
+pub const CHUNK_SIZE: usize = 58;
+
+/// Usage page of the HID interface that takes configuration reports.
+pub const CONFIG_USAGE_PAGE: u16 = 0xfff0;
+
+/// Usage page of the HID interface that sends gamepad input reports.
+pub const GAMEPAD_USAGE_PAGE: u16 = 0xff00;
+
+/// First byte of reports sent by the host.
+pub const HOST_PREFIX: u8 = 0x0f;
+
+/// First byte of replies from the device. The app's decoder expects `0x0F`,
+/// but the controller sends `0x10`.
+pub const DEVICE_PREFIX: u8 = 0x10;
+
+/// First byte of gamepad input reports.
+pub const INPUT_REPORT_ID: u8 = 0x12;
+
+/// Second byte of the report the controller sends unprompted when a profile
+/// is changed on the controller itself.
+pub const PROFILE_CHANGED: u8 = 0x0f;
+
+/// Marker bytes following the `Vibration` command.
+pub const VIBRATION_MAGIC: [u8; 2] = [0x66, 0x55];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    HostToDevice,
+    DeviceToHost,
+}
+
+/// An entry in the command table, keyed by the second byte of a report.
+#[derive(Clone, Copy, Debug)]
+pub struct CommandInfo {
+    pub id: u8,
+    pub name: &'static str,
+    pub direction: Direction,
+    /// Whether the command changes state on the controller.
+    pub mutating: bool,
+    /// Parameters following the command byte, where they are known.
+    pub params: &'static [Param],
+}
+
+impl CommandInfo {
+    const fn with_params(self, params: &'static [Param]) -> CommandInfo {
+        CommandInfo { params, ..self }
+    }
+}
+
+/// A parameter of a command, at a fixed offset in the report.
+#[derive(Clone, Copy, Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub offset: usize,
+    pub kind: ParamKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamKind {
+    U8,
+    /// A profile index, shown as a [`ProfileId`] where it is one.
+    Profile,
+    /// A big-endian offset into a profile.
+    Offset,
+    /// Fixed bytes that the controller checks before acting on the command.
+    Magic(&'static [u8]),
+    /// Profile bytes, as many as given by the `length` parameter.
+    Data,
+}
+
+impl ParamKind {
+    pub fn size(&self) -> usize {
+        match self {
+            ParamKind::U8 | ParamKind::Profile => 1,
+            ParamKind::Offset => 2,
+            ParamKind::Magic(bytes) => bytes.len(),
+            ParamKind::Data => 0,
+        }
+    }
+}
+
+const fn param(name: &'static str, offset: usize, kind: ParamKind) -> Param {
+    Param { name, offset, kind }
+}
+
+const fn host(id: u8, name: &'static str, mutating: bool) -> CommandInfo {
+    CommandInfo {
+        id,
+        name,
+        direction: Direction::HostToDevice,
+        mutating,
+        params: &[],
+    }
+}
+
+const fn device(id: u8, name: &'static str) -> CommandInfo {
+    CommandInfo {
+        id,
+        name,
+        direction: Direction::DeviceToHost,
+        mutating: false,
+        params: &[],
+    }
+}
+
+const PROFILE: &[Param] = &[param("profile", 2, ParamKind::Profile)];
+
+const PROFILE_RANGE: &[Param] = &[
+    param("profile", 2, ParamKind::Profile),
+    param("offset", 3, ParamKind::Offset),
+    param("length", 5, ParamKind::U8),
+];
+
+const PROFILE_CHUNK: &[Param] = &[
+    param("profile", 2, ParamKind::Profile),
+    param("offset", 3, ParamKind::Offset),
+    param("length", 5, ParamKind::U8),
+    param("data", 6, ParamKind::Data),
+];
+
+pub const COMMANDS: &[CommandInfo] = &[
+    host(0x01, "EnterProfileConfig", true),
+    host(0x02, "ExitProfileConfig", true),
+    host(0x03, "WriteProfile", true).with_params(PROFILE_CHUNK),
+    host(0x04, "ReadProfile", false).with_params(PROFILE_RANGE),
+    device(0x05, "ReadProfileAck").with_params(PROFILE_CHUNK),
+    device(0x06, "Ack").with_params(&[param("busy", 2, ParamKind::U8)]),
+    host(0x07, "SwitchProfile", true).with_params(PROFILE),
+    host(0x08, "WriteProfileToEEPRom", true),
+    host(0x09, "ReadFirmwareVersion", false),
+    device(0x0a, "ReadFirmwareVersionAck"),
+    host(0x0b, "ReadCurrentProfile", false),
+    device(0x0c, "ReadCurrentProfileAck").with_params(PROFILE),
+    host(0x0d, "SetRGB", true),
+    device(0x0e, "ReadRGBAck"),
+    device(0x0f, "ProfileChanged"),
+    host(0x10, "RefreshProfile", false).with_params(PROFILE_RANGE),
+    device(0x11, "RefreshProfileAck").with_params(PROFILE_CHUNK),
+    host(0x12, "WriteEEPRom", true),
+    device(0x13, "WriteEEPRomAck"),
+    host(0x14, "ReadEEPRom", false),
+    device(0x15, "ReadEEPRomAck"),
+    host(0x16, "SetMacroStatus", true),
+    host(0x17, "QuickUpdate", true),
+    host(0x20, "Vibration", true).with_params(&[
+        param("magic", 2, ParamKind::Magic(&VIBRATION_MAGIC)),
+        param("left", 4, ParamKind::U8),
+        param("right", 5, ParamKind::U8),
+    ]),
+    host(0xf0, "Download", true),
+    device(0xf1, "DownloadAck"),
+    host(0xf2, "HeartBeat", false).with_params(&[param("test_mode", 2, ParamKind::U8)]),
+    host(0xf3, "ReadKeyStatus", false),
+    device(0xf4, "ReadKeyStatusAck"),
+    host(0xfc, "RequestToUpgrade", true),
+    host(0xfd, "SetCalibrationState", true),
+];
+
+pub fn command(id: u8) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|c| c.id == id)
+}
+
+/// A field of the gamepad input report.
+#[derive(Clone, Copy, Debug)]
+pub struct InputField {
+    pub name: &'static str,
+    pub label: &'static str,
+    pub offset: usize,
+    /// Number of bytes, shown as hex if more than one.
+    pub size: usize,
+    /// Bits of the byte holding the field, for fields smaller than a byte.
+    pub mask: Option<u8>,
+}
+
+impl InputField {
+    /// Reads the field from an input report.
+    pub fn read(&self, report: &[u8]) -> Option<u32> {
+        let bytes = report.get(self.offset..self.offset + self.size)?;
+        let value = bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u32);
+        Some(match self.mask {
+            Some(mask) => (value & mask as u32) >> mask.trailing_zeros(),
+            None => value,
+        })
+    }
+}
+
+const fn input(name: &'static str, label: &'static str, offset: usize) -> InputField {
+    InputField {
+        name,
+        label,
+        offset,
+        size: 1,
+        mask: None,
+    }
+}
+
+const fn input_bits(
+    name: &'static str,
+    label: &'static str,
+    offset: usize,
+    mask: u8,
+) -> InputField {
+    InputField {
+        name,
+        label,
+        offset,
+        size: 1,
+        mask: Some(mask),
+    }
+}
+
+const fn input_rgb(name: &'static str, label: &'static str, offset: usize) -> InputField {
+    InputField {
+        name,
+        label,
+        offset,
+        size: 3,
+        mask: None,
+    }
+}
+
+/// Size of the part of the input report that [`INPUT_FIELDS`] covers.
+pub const INPUT_REPORT_LEN: usize = 54;
+
+/// Fields of the gamepad input report (report ID `0x12`).
+pub const INPUT_FIELDS: &[InputField] = &[
+    input("left_stick_x", "Left stick X", 1),
+    input("left_stick_y", "Left stick Y", 2),
+    input("right_stick_x", "Right stick X", 3),
+    input("right_stick_y", "Right stick Y", 4),
+    input_bits("dpad", "D-pad", 5, 0x0f),
+    input_bits("square", "Square / X", 5, 0x10),
+    input_bits("cross", "Cross / A", 5, 0x20),
+    input_bits("circle", "Circle / B", 5, 0x40),
+    input_bits("triangle", "Triangle / Y", 5, 0x80),
+    input_bits("l1", "L1", 6, 0x01),
+    input_bits("r1", "R1", 6, 0x02),
+    input_bits("l2", "L2 (digital)", 6, 0x04),
+    input_bits("r2", "R2 (digital)", 6, 0x08),
+    input_bits("select", "Select", 6, 0x10),
+    input_bits("start", "Start", 6, 0x20),
+    input_bits("l3", "L3", 6, 0x40),
+    input_bits("r3", "R3", 6, 0x80),
+    input_bits("home", "Home", 7, 0x01),
+    input_bits("capture", "Capture", 7, 0x02),
+    input_bits("fl1", "FL1", 7, 0x08),
+    input_bits("fr1", "FR1", 7, 0x10),
+    input_bits("m", "M", 7, 0x20),
+    input("left_trigger", "Left trigger", 8),
+    input("right_trigger", "Right trigger", 9),
+    input("charging", "Charging", 35),
+    input("battery", "Battery level", 36),
+    input("active_profile", "Active profile (0-based)", 37),
+    input_rgb("home_led", "Home LED", 38),
+    input_rgb("lower_left_led", "Lower-left LED", 41),
+    input_rgb("lower_right_led", "Lower-right LED", 44),
+    input_rgb("upper_left_led", "Upper-left LED", 47),
+    input_rgb("upper_right_led", "Upper-right LED", 50),
+    input_bits("macro_recording", "Macro recording", 53, 0x01),
+    input_bits("macro_playback", "Macro playback", 53, 0x02),
+    input_bits("macro_key_fl1", "FL1 is the macro record key", 53, 0x10),
+    input_bits("macro_key_fr1", "FR1 is the macro record key", 53, 0x20),
+];
+
+/// Describes a report in one line, e.g.
+/// `WriteProfile profile=2 offset=0x0040 length=7`.
+pub fn annotate(report: &[u8]) -> String {
+    let [prefix, id, ..] = report else {
+        return format!("short report ({} bytes)", report.len());
+    };
+
+    let direction = match *prefix {
+        HOST_PREFIX => Direction::HostToDevice,
+        DEVICE_PREFIX => Direction::DeviceToHost,
+        INPUT_REPORT_ID => return "InputReport".to_owned(),
+        _ => return format!("unknown report {prefix:#04x}"),
+    };
+    let info = match command(*id) {
+        Some(info) if info.direction == direction => info,
+        _ => return format!("unknown command {prefix:#04x} {id:#04x}"),
+    };
+
+    let mut details = Vec::new();
+    for param in info.params {
+        let bytes = report.get(param.offset..param.offset + param.kind.size());
+        let value = match (param.kind, bytes) {
+            (ParamKind::Data, _) => continue,
+            // Without the magic bytes, the controller ignores the command,
+            // so the other parameters mean nothing
+            (ParamKind::Magic(magic), bytes) if bytes != Some(magic) => {
+                details = vec!["(bad magic)".to_owned()];
+                break;
+            }
+            (ParamKind::Magic(_), _) => continue,
+            (_, None) => "?".to_owned(),
+            (ParamKind::U8, Some(bytes)) => bytes[0].to_string(),
+            (ParamKind::Profile, Some(bytes)) => match ProfileId::from_index(bytes[0]) {
+                Some(id) => id.to_string(),
+                None => bytes[0].to_string(),
+            },
+            (ParamKind::Offset, Some(bytes)) => {
+                format!("{:#06x}", u16::from_be_bytes([bytes[0], bytes[1]]))
+            }
+        };
+        details.push(format!("{}={value}", param.name));
+    }
+    let details = details.join(" ");
+
+    if details.is_empty() {
+        info.name.to_owned()
+    } else {
+        format!("{} {details}", info.name)
+    }
+}
@@ -0,0 +1,514 @@
+//! Lookup of encoded fields by path, such as `left_stick.front_dead` or
+//! `fn_mappings[FR1].steps[4]`.
+//!
+//! Fields of modules marked `#[layout(flatten)]` can be named directly, so
+//! `left_stick.front_dead` and `left_stick.dead_module.front_dead` refer to
+//! the same byte. Elements of labelled arrays can be indexed by label or by
+//! number, so `mappings[Cross]` and `mappings[8]` are the same mapping.
+
+use eyre::{OptionExt, bail, ensure, eyre};
+
+use crate::driver::layout::{Field, FieldKind, Shape, read_string, write_string};
+
+/// A field located within an encoded value.
+#[derive(Clone, Debug)]
+pub struct FieldRef {
+    pub path: String,
+    pub offset: usize,
+    pub size: usize,
+    pub shape: &'static Shape,
+    /// Kind of the field, or of the outermost reserved or unknown field that
+    /// contains it.
+    pub kind: FieldKind,
+}
+
+impl FieldRef {
+    pub fn bytes<'a>(&self, bytes: &'a [u8]) -> &'a [u8] {
+        &bytes[self.offset..self.offset + self.size]
+    }
+
+    /// Whether the field holds a single value rather than a struct or array.
+    pub fn is_leaf(&self) -> bool {
+        matches!(self.shape, Shape::Value(_) | Shape::String)
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self.shape {
+            Shape::Value(value) => value.type_name,
+            Shape::String => "String",
+            Shape::Struct(_) => "struct",
+            Shape::Array { .. } => "array",
+        }
+    }
+
+    /// Formats the value of a leaf field.
+    pub fn format(&self, bytes: &[u8]) -> eyre::Result<String> {
+        let field_bytes = self.bytes(bytes);
+        match self.shape {
+            Shape::Value(value) => (value.format)(field_bytes),
+            Shape::String => read_string(&mut &*field_bytes, self.size),
+            _ => bail!("{} is not a single value", self.path),
+        }
+    }
+
+    /// Parses `value` and stores it in a leaf field.
+    pub fn set(&self, bytes: &mut [u8], value: &str) -> eyre::Result<()> {
+        let encoded = match self.shape {
+            Shape::Value(shape) => (shape.parse)(value)?,
+            Shape::String => {
+                // The last byte is left as a NUL, as the app does
+                ensure!(
+                    value.len() < self.size,
+                    "{} can hold at most {} bytes",
+                    self.path,
+                    self.size - 1
+                );
+                let mut encoded = Vec::with_capacity(self.size);
+                write_string(value, &mut encoded, self.size)?;
+                encoded
+            }
+            _ => bail!("{} is not a single value", self.path),
+        };
+        ensure!(encoded.len() == self.size);
+        bytes[self.offset..self.offset + self.size].copy_from_slice(&encoded);
+        Ok(())
+    }
+
+    /// Returns every leaf field within this one.
+    pub fn leaves(&self) -> Vec<FieldRef> {
+        let mut leaves = Vec::new();
+        collect_leaves(self.clone(), self.offset + self.size, &mut leaves);
+        leaves
+    }
+}
+
+/// Returns a reference to the whole of a value with the given shape.
+pub fn root(shape: &'static Shape, size: usize) -> FieldRef {
+    FieldRef {
+        path: String::new(),
+        offset: 0,
+        size,
+        shape,
+        kind: FieldKind::Known,
+    }
+}
+
+/// Resolves a dotted path within a value with the given shape.
+pub fn resolve(shape: &'static Shape, size: usize, path: &str) -> eyre::Result<FieldRef> {
+    let mut current = root(shape, size);
+    // End of the bytes actually stored for the current field, which can be
+    // less than its nominal size within trimmed arrays.
+    let mut end = size;
+
+    for segment in path.split('.') {
+        let (name, mut indices) = match segment.split_once('[') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (segment, None),
+        };
+
+        let Shape::Struct(fields) = current.shape else {
+            bail!("{} has no field named {name}", describe(&current));
+        };
+        let (offset, field) = find_field(fields, name)?
+            .ok_or_else(|| eyre!("{} has no field named {name}", describe(&current)))?;
+
+        current = FieldRef {
+            path: join(&current.path, name),
+            offset: current.offset + offset,
+            size: field.size,
+            shape: field.shape,
+            kind: inherit(current.kind, field.kind),
+        };
+        end = end.min(current.offset + field.size);
+
+        while let Some(rest) = indices {
+            let (index, rest) = rest
+                .split_once(']')
+                .ok_or_eyre(format!("missing ']' in {segment}"))?;
+            let index = parse_index(&current, index.trim())
+                .ok_or_else(|| eyre!("invalid index in {segment}: {index}"))?;
+            current = index_into(&current, index)?;
+            indices = match rest {
+                "" => None,
+                rest => Some(
+                    rest.strip_prefix('[')
+                        .ok_or_eyre(format!("unexpected characters after ']' in {segment}"))?,
+                ),
+            };
+        }
+    }
+
+    if current.offset + current.size > end {
+        ensure!(!current.is_leaf(), "{} is not stored", current.path);
+        current.size = end.saturating_sub(current.offset);
+    }
+
+    Ok(current)
+}
+
+fn inherit(parent: FieldKind, child: FieldKind) -> FieldKind {
+    match parent {
+        FieldKind::Known => child,
+        _ => parent,
+    }
+}
+
+fn describe(field: &FieldRef) -> String {
+    if field.path.is_empty() {
+        "profile".to_owned()
+    } else {
+        field.path.clone()
+    }
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{path}.{name}")
+    }
+}
+
+/// Finds a field by name, looking through flattened fields. Returns the
+/// field's offset relative to the start of `fields`.
+fn find_field(
+    fields: &'static [Field],
+    name: &str,
+) -> eyre::Result<Option<(usize, &'static Field)>> {
+    if let Some(field) = fields.iter().find(|f| f.name == name) {
+        return Ok(Some((field.offset, field)));
+    }
+
+    let mut found = None;
+    for parent in fields.iter().filter(|f| f.flatten) {
+        let Shape::Struct(children) = parent.shape else {
+            continue;
+        };
+        if let Some((offset, field)) = find_field(children, name)? {
+            ensure!(found.is_none(), "{name} is ambiguous");
+            found = Some((parent.offset + offset, field));
+        }
+    }
+
+    Ok(found)
+}
+
+fn parse_index(array: &FieldRef, index: &str) -> Option<usize> {
+    if let Ok(index) = index.parse() {
+        return Some(index);
+    }
+    let Shape::Array {
+        labels: Some(labels),
+        ..
+    } = array.shape
+    else {
+        return None;
+    };
+    labels.iter().position(|l| l.eq_ignore_ascii_case(index))
+}
+
+fn element_path(path: &str, labels: Option<&[&str]>, index: usize) -> String {
+    match labels {
+        Some(labels) => format!("{path}[{}]", labels[index]),
+        None => format!("{path}[{index}]"),
+    }
+}
+
+fn index_into(array: &FieldRef, index: usize) -> eyre::Result<FieldRef> {
+    let Shape::Array {
+        elem,
+        elem_size,
+        len,
+        labels,
+    } = array.shape
+    else {
+        bail!("{} is not an array", array.path);
+    };
+
+    ensure!(
+        index < *len,
+        "index {index} is out of bounds for {}, which has {len} elements",
+        array.path
+    );
+
+    Ok(FieldRef {
+        path: element_path(&array.path, *labels, index),
+        offset: array.offset + index * elem_size,
+        size: *elem_size,
+        shape: elem,
+        kind: array.kind,
+    })
+}
+
+fn collect_leaves(field: FieldRef, end: usize, leaves: &mut Vec<FieldRef>) {
+    match field.shape {
+        Shape::Value(_) | Shape::String => {
+            // The tail of a trimmed array is not stored
+            if field.offset + field.size <= end {
+                leaves.push(field);
+            }
+        }
+        Shape::Struct(fields) => {
+            for child in fields.iter() {
+                let child_ref = FieldRef {
+                    path: if child.flatten {
+                        field.path.clone()
+                    } else {
+                        join(&field.path, child.name)
+                    },
+                    offset: field.offset + child.offset,
+                    size: child.size,
+                    shape: child.shape,
+                    kind: inherit(field.kind, child.kind),
+                };
+                let child_end = end.min(child_ref.offset + child.size);
+                collect_leaves(child_ref, child_end, leaves);
+            }
+        }
+        Shape::Array {
+            elem,
+            elem_size,
+            len,
+            labels,
+        } => {
+            for i in 0..*len {
+                let elem_ref = FieldRef {
+                    path: element_path(&field.path, *labels, i),
+                    offset: field.offset + i * elem_size,
+                    size: *elem_size,
+                    shape: elem,
+                    kind: field.kind,
+                };
+                collect_leaves(elem_ref, end, leaves);
+            }
+        }
+    }
+}
+
+/// A field whose value differs between two encodings.
+#[derive(Clone, Debug)]
+pub struct Change {
+    /// Path of the field, or `<reserved>` for bytes not covered by any field.
+    pub path: String,
+    pub offset: usize,
+    pub size: usize,
+    pub kind: FieldKind,
+    pub old: String,
+    pub new: String,
+}
+
+/// Compares two encodings of a value with the given shape field by field.
+///
+/// Differences in bytes that no field covers are reported as `<reserved>`
+/// changes spanning the bytes that differ.
+pub fn diff(shape: &'static Shape, size: usize, old: &[u8], new: &[u8]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut pos = 0;
+
+    for leaf in root(shape, size).leaves() {
+        diff_gap(pos, leaf.offset, old, new, &mut changes);
+        if leaf.bytes(old) != leaf.bytes(new) {
+            changes.push(Change {
+                old: format_for_diff(&leaf, old),
+                new: format_for_diff(&leaf, new),
+                path: leaf.path,
+                offset: leaf.offset,
+                size: leaf.size,
+                kind: leaf.kind,
+            });
+        }
+        pos = leaf.offset + leaf.size;
+    }
+    diff_gap(pos, size, old, new, &mut changes);
+
+    changes
+}
+
+fn format_for_diff(field: &FieldRef, bytes: &[u8]) -> String {
+    match field.format(bytes) {
+        Ok(value) if matches!(field.shape, Shape::String) => format!("{value:?}"),
+        Ok(value) => value,
+        Err(e) => format!("invalid ({e})"),
+    }
+}
+
+fn diff_gap(start: usize, end: usize, old: &[u8], new: &[u8], changes: &mut Vec<Change>) {
+    if start >= end {
+        return;
+    }
+    let differs = |i: &usize| old[*i] != new[*i];
+    let Some(first) = (start..end).find(differs) else {
+        return;
+    };
+    let last = (start..end).rfind(differs).unwrap();
+    let hex = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    changes.push(Change {
+        path: "<reserved>".to_owned(),
+        offset: first,
+        size: last + 1 - first,
+        kind: FieldKind::Reserved,
+        old: hex(&old[first..=last]),
+        new: hex(&new[first..=last]),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{ControlProfile, Flag, Layout};
+
+    /// Resolves a path within a control profile, returning its offset and
+    /// size.
+    fn control(path: &str) -> eyre::Result<(usize, usize)> {
+        let field = resolve(ControlProfile::SHAPE, ControlProfile::SIZE, path)?;
+        Ok((field.offset, field.size))
+    }
+
+    fn error(path: &str) -> String {
+        control(path).unwrap_err().to_string()
+    }
+
+    // Offsets are those given in C2_PROTOCOL.md §7
+    #[test]
+    fn resolves_known_offsets() {
+        assert_eq!(control("name").unwrap(), (0, 32));
+        assert_eq!(control("shift_value").unwrap(), (42, 1));
+        assert_eq!(control("mappings").unwrap(), (64, 112));
+        assert_eq!(control("mappings[3].map[0]").unwrap(), (88, 1));
+        assert_eq!(control("fn_mappings[0]").unwrap(), (176, 159));
+        assert_eq!(control("fn_mappings[1].steps[4]").unwrap(), (366, 5));
+        assert_eq!(
+            control("fn_mappings[1].steps[4].step_delay_time").unwrap(),
+            (369, 2)
+        );
+        assert_eq!(control("left_trigger").unwrap(), (494, 28));
+        assert_eq!(control("right_stick").unwrap(), (582, 32));
+        assert_eq!(control("tilt_sensor").unwrap(), (647, 33));
+    }
+
+    #[test]
+    fn labels_index_arrays() {
+        assert_eq!(control("mappings[Cross]").unwrap(), (120, 7));
+        assert_eq!(control("mappings[cross]").unwrap(), (120, 7));
+        assert_eq!(control("mappings[8]").unwrap(), (120, 7));
+        assert_eq!(control("fn_mappings[FR1]").unwrap(), (335, 159));
+
+        let field = resolve(ControlProfile::SHAPE, ControlProfile::SIZE, "mappings[8]").unwrap();
+        assert_eq!(field.path, "mappings[Cross]");
+    }
+
+    #[test]
+    fn flattened_fields_can_be_named_directly() {
+        assert_eq!(control("left_stick.front_dead").unwrap(), (553, 1));
+        assert_eq!(
+            control("left_stick.dead_module.front_dead").unwrap(),
+            (553, 1)
+        );
+        // Through two levels of flattening
+        assert_eq!(control("fn_mappings[FR1].turbo_speed").unwrap(), (336, 1));
+        assert_eq!(control("fn_mappings[FR1].map[0]").unwrap(), (338, 1));
+    }
+
+    #[derive(Layout)]
+    struct Speed {
+        speed: u8,
+    }
+
+    #[derive(Layout)]
+    struct Ambiguous {
+        #[layout(flatten)]
+        first: Speed,
+        #[layout(flatten)]
+        second: Speed,
+    }
+
+    #[test]
+    fn ambiguous_names_are_rejected() {
+        let err = resolve(Ambiguous::SHAPE, Ambiguous::SIZE, "speed").unwrap_err();
+        assert_eq!(err.to_string(), "speed is ambiguous");
+        let field = resolve(Ambiguous::SHAPE, Ambiguous::SIZE, "second.speed").unwrap();
+        assert_eq!(field.offset, 1);
+    }
+
+    #[test]
+    fn trimmed_steps_are_not_stored() {
+        // The last step has no delay, so it ends with the function key
+        assert_eq!(control("fn_mappings[1].steps[29]").unwrap(), (491, 3));
+        assert_eq!(
+            control("fn_mappings[1].steps[29].step_hold_time").unwrap(),
+            (492, 2)
+        );
+        assert_eq!(
+            error("fn_mappings[1].steps[29].step_delay_time"),
+            "fn_mappings[FR1].steps[29].step_delay_time is not stored"
+        );
+        assert_eq!(control("fn_mappings[1].steps").unwrap(), (346, 148));
+    }
+
+    #[test]
+    fn bad_paths_are_rejected() {
+        assert_eq!(
+            error("mappings[16]"),
+            "index 16 is out of bounds for mappings, which has 16 elements"
+        );
+        assert_eq!(
+            error("fn_mappings[1].steps[30]"),
+            "index 30 is out of bounds for fn_mappings[FR1].steps, which has 30 elements"
+        );
+        assert_eq!(
+            error("mappings[Paddle]"),
+            "invalid index in mappings[Paddle]: Paddle"
+        );
+        assert_eq!(error("mappings[3"), "missing ']' in mappings[3");
+        assert_eq!(
+            error("mappings[3]x"),
+            "unexpected characters after ']' in mappings[3]x"
+        );
+        assert_eq!(error("name[0]"), "name is not an array");
+        assert_eq!(error("colour"), "profile has no field named colour");
+        assert_eq!(
+            error("left_stick.colour"),
+            "left_stick has no field named colour"
+        );
+        assert_eq!(error("name.first"), "name has no field named first");
+    }
+
+    #[test]
+    fn set_limits_string_length() {
+        let mut bytes = vec![0; ControlProfile::SIZE];
+        let name = resolve(ControlProfile::SHAPE, ControlProfile::SIZE, "name").unwrap();
+
+        let longest = "a".repeat(ControlProfile::MAX_NAME_LEN);
+        name.set(&mut bytes, &longest).unwrap();
+        assert_eq!(name.format(&bytes).unwrap(), longest);
+        // The last byte is left as a NUL
+        assert_eq!(bytes[31], 0);
+
+        let err = name.set(&mut bytes, &"a".repeat(32)).unwrap_err();
+        assert_eq!(err.to_string(), "name can hold at most 31 bytes");
+        let err = name.set(&mut bytes, &"é".repeat(16)).unwrap_err();
+        assert_eq!(err.to_string(), "name can hold at most 31 bytes");
+        assert_eq!(name.format(&bytes).unwrap(), longest);
+    }
+
+    #[test]
+    fn set_stores_values_at_their_offset() {
+        let mut bytes = vec![0; ControlProfile::SIZE];
+        let field = resolve(
+            ControlProfile::SHAPE,
+            ControlProfile::SIZE,
+            "left_stick.map_en",
+        )
+        .unwrap();
+        field.set(&mut bytes, "true").unwrap();
+        let profile = ControlProfile::read(&mut &*bytes).unwrap();
+        assert_eq!(profile.left_stick.map_module.map_en, Flag::ON);
+        assert_eq!(bytes.iter().filter(|&&b| b != 0).count(), 1);
+    }
+}
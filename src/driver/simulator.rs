@@ -0,0 +1,224 @@
+//! An in-memory model of a controller, for exercising the driver without
+//! hardware.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::driver::backup::Backup;
+use crate::driver::protocol::{DEVICE_PREFIX, HOST_PREFIX, REPORT_SIZE, VIBRATION_MAGIC};
+use crate::driver::transport::{TimeoutError, Transport};
+use crate::driver::{FirmwareVersion, ProfileId};
+
+/// Transport that answers commands the way the controller does, from
+/// profiles held in memory.
+///
+/// Clones share the same state, so one can be handed to
+/// [`Cyclone2`](crate::driver::Cyclone2) while another is kept to inspect
+/// what the controller would hold. The state is behind a mutex, so clones
+/// can also be used from other threads, such as to serve a virtual device.
+///
+/// Where the controller's behaviour isn't known, the model picks something
+/// plausible: reads past the end of a profile return zeros, writes past the
+/// end are acked but dropped, and commands it doesn't know are ignored.
+#[derive(Clone)]
+pub struct Simulator {
+    state: Arc<Mutex<State>>,
+    inputs: (kanal::Sender<[u8; 64]>, kanal::Receiver<[u8; 64]>),
+    notifications: (kanal::Sender<[u8; 64]>, kanal::Receiver<[u8; 64]>),
+}
+
+struct State {
+    profiles: HashMap<ProfileId, Vec<u8>>,
+    firmware: FirmwareVersion,
+    /// Index of the active profile, 0 until one is selected.
+    current_profile: u8,
+    vibration: (u8, u8),
+    replies: VecDeque<[u8; REPORT_SIZE]>,
+}
+
+impl Default for Simulator {
+    fn default() -> Simulator {
+        Simulator::new()
+    }
+}
+
+impl Simulator {
+    /// Creates a controller whose profiles are all zeros.
+    pub fn new() -> Simulator {
+        let profiles = ProfileId::ALL
+            .into_iter()
+            .map(|id| (id, vec![0; id.kind().size()]))
+            .collect();
+        Simulator {
+            state: Arc::new(Mutex::new(State {
+                profiles,
+                firmware: FirmwareVersion {
+                    controller: "1.0.0".to_owned(),
+                    dongle: "1.0.0".to_owned(),
+                },
+                current_profile: 0,
+                vibration: (0, 0),
+                replies: VecDeque::new(),
+            })),
+            inputs: kanal::unbounded(),
+            notifications: kanal::unbounded(),
+        }
+    }
+
+    /// Creates a controller holding what was saved in a backup.
+    pub fn from_backup(backup: &Backup) -> Simulator {
+        let simulator = Simulator::new();
+        {
+            let mut state = simulator.state.lock();
+            for profile in &backup.profiles {
+                state.profiles.insert(profile.id, profile.bytes.clone());
+            }
+            state.firmware = backup.firmware.clone();
+            state.current_profile = backup.current_profile.index();
+        }
+        simulator
+    }
+
+    pub fn profile(&self, id: ProfileId) -> Vec<u8> {
+        self.state.lock().profiles[&id].clone()
+    }
+
+    pub fn set_profile(&self, id: ProfileId, bytes: &[u8]) {
+        let mut state = self.state.lock();
+        let profile = state.profiles.get_mut(&id).unwrap();
+        let len = bytes.len().min(profile.len());
+        profile[..len].copy_from_slice(&bytes[..len]);
+    }
+
+    /// Index of the active profile, as the controller reports it.
+    pub fn current_profile(&self) -> u8 {
+        self.state.lock().current_profile
+    }
+
+    /// Strengths last given to the rumble motors.
+    pub fn vibration(&self) -> (u8, u8) {
+        self.state.lock().vibration
+    }
+
+    /// Sends an input report, as the controller does while it's used.
+    pub fn send_input(&self, report: [u8; 64]) {
+        let _ = self.inputs.0.send(report);
+    }
+
+    /// Sends a report unprompted, such as `ProfileChanged`.
+    pub fn send_notification(&self, report: [u8; 64]) {
+        let _ = self.notifications.0.send(report);
+    }
+
+    /// Whether replies are waiting to be read.
+    pub fn has_pending_replies(&self) -> bool {
+        !self.state.lock().replies.is_empty()
+    }
+}
+
+impl State {
+    fn reply(&mut self, bytes: &[u8]) {
+        let mut reply = [0; REPORT_SIZE];
+        reply[..bytes.len()].copy_from_slice(bytes);
+        self.replies.push_back(reply);
+    }
+
+    fn profile_chunk(&self, report: &[u8; REPORT_SIZE]) -> Option<(ProfileId, usize, usize)> {
+        let id = ProfileId::from_index(report[2])?;
+        let offset = u16::from_be_bytes([report[3], report[4]]) as usize;
+        let length = (report[5] as usize).min(REPORT_SIZE - 6);
+        Some((id, offset, length))
+    }
+}
+
+impl Transport for Simulator {
+    fn write(&self, data: &[u8]) -> eyre::Result<()> {
+        let mut report = [0; REPORT_SIZE];
+        let len = data.len().min(REPORT_SIZE);
+        report[..len].copy_from_slice(&data[..len]);
+
+        let mut state = self.state.lock();
+        if report[0] != HOST_PREFIX {
+            return Ok(());
+        }
+
+        match report[1] {
+            0x03 => {
+                let Some((id, offset, length)) = state.profile_chunk(&report) else {
+                    return Ok(());
+                };
+                let profile = state.profiles.get_mut(&id).unwrap();
+                for (i, byte) in report[6..6 + length].iter().enumerate() {
+                    if let Some(stored) = profile.get_mut(offset + i) {
+                        *stored = *byte;
+                    }
+                }
+                state.reply(&[DEVICE_PREFIX, 0x06, 0]);
+            }
+            0x04 => {
+                let Some((id, offset, length)) = state.profile_chunk(&report) else {
+                    return Ok(());
+                };
+                let profile = &state.profiles[&id];
+                let mut reply = [0; REPORT_SIZE];
+                reply[..6].copy_from_slice(&report[..6]);
+                reply[0] = DEVICE_PREFIX;
+                reply[1] = 0x05;
+                for (i, byte) in reply[6..6 + length].iter_mut().enumerate() {
+                    *byte = profile.get(offset + i).copied().unwrap_or(0);
+                }
+                state.replies.push_back(reply);
+            }
+            0x07 => {
+                if matches!(ProfileId::from_index(report[2]), Some(id) if id != ProfileId::Light) {
+                    state.current_profile = report[2];
+                }
+                state.reply(&[DEVICE_PREFIX, 0x06, 0]);
+            }
+            0x09 => {
+                let mut reply = [0; 17];
+                reply[..2].copy_from_slice(&[DEVICE_PREFIX, 0x0a]);
+                encode_version(&state.firmware.controller, &mut reply[4..9]);
+                encode_version(&state.firmware.dongle, &mut reply[12..17]);
+                state.reply(&reply);
+            }
+            0x0b => {
+                let current = state.current_profile;
+                state.reply(&[DEVICE_PREFIX, 0x0c, current]);
+            }
+            0x20 if report[2..4] == VIBRATION_MAGIC => {
+                state.vibration = (report[4], report[5]);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn read_timeout(&self, _timeout: Duration) -> Result<[u8; 64], TimeoutError> {
+        self.state
+            .lock()
+            .replies
+            .pop_front()
+            .ok_or(TimeoutError::Timeout)
+    }
+
+    fn input_reports(&self) -> Option<kanal::Receiver<[u8; 64]>> {
+        Some(self.inputs.1.clone())
+    }
+
+    fn notifications(&self) -> Option<kanal::Receiver<[u8; 64]>> {
+        Some(self.notifications.1.clone())
+    }
+}
+
+/// Encodes a version such as `1.0.9` the way the controller sends it, with
+/// NULs in place of the dots.
+fn encode_version(version: &str, out: &mut [u8]) {
+    for (out, byte) in out.iter_mut().zip(version.bytes()) {
+        *out = if byte == b'.' { 0 } else { byte };
+    }
+}
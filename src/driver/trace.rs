@@ -0,0 +1,219 @@
+//! Recording of the reports exchanged with a controller, and replay of them
+//! in place of the controller.
+//!
+//! Traces are JSON Lines files: a header giving the format version and the
+//! controller's product ID, then one line per report.
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use eyre::{OptionExt, WrapErr, bail, ensure};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::driver::Variant;
+use crate::driver::protocol;
+use crate::driver::transport::{TimeoutError, Transport};
+
+/// Version of the trace format written by this build.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub product_id: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// Microseconds since recording started.
+    pub elapsed_us: u64,
+    pub direction: Direction,
+    #[serde(with = "crate::driver::hex")]
+    pub data: Vec<u8>,
+}
+
+/// Reads a whole trace.
+pub fn load(path: &Path) -> eyre::Result<(Header, Vec<Entry>)> {
+    let text =
+        fs::read_to_string(path).wrap_err_with(|| format!("failed to read {}", path.display()))?;
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+
+    let (_, header) = lines.next().ok_or_eyre("trace is empty")?;
+    let header: Header = serde_json::from_str(header).wrap_err("invalid trace header")?;
+    ensure!(
+        header.version == FORMAT_VERSION,
+        "unsupported trace version {}, expected {FORMAT_VERSION}",
+        header.version
+    );
+
+    let entries = lines
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .wrap_err_with(|| format!("invalid trace entry on line {}", i + 1))
+        })
+        .collect::<eyre::Result<_>>()?;
+
+    Ok((header, entries))
+}
+
+/// Transport that passes everything through to another transport, writing
+/// each report to a trace file.
+pub struct Recording {
+    inner: Box<dyn Transport>,
+    file: Mutex<File>,
+    start: Instant,
+}
+
+impl Recording {
+    pub fn create(
+        inner: Box<dyn Transport>,
+        variant: Variant,
+        path: &Path,
+    ) -> eyre::Result<Recording> {
+        let mut file =
+            File::create(path).wrap_err_with(|| format!("failed to create {}", path.display()))?;
+        let header = Header {
+            version: FORMAT_VERSION,
+            product_id: variant.product_id(),
+        };
+        writeln!(file, "{}", serde_json::to_string(&header)?)?;
+
+        Ok(Recording {
+            inner,
+            file: Mutex::new(file),
+            start: Instant::now(),
+        })
+    }
+
+    fn log(&self, direction: Direction, data: &[u8]) -> eyre::Result<()> {
+        let entry = Entry {
+            elapsed_us: self.start.elapsed().as_micros() as u64,
+            direction,
+            data: data.to_vec(),
+        };
+        // Written unbuffered so that the trace is complete even if the
+        // program crashes
+        writeln!(self.file.lock(), "{}", serde_json::to_string(&entry)?)?;
+        Ok(())
+    }
+}
+
+impl Transport for Recording {
+    fn write(&self, data: &[u8]) -> eyre::Result<()> {
+        self.log(Direction::Sent, data)?;
+        self.inner.write(data)
+    }
+
+    fn read_timeout(&self, timeout: Duration) -> Result<[u8; 64], TimeoutError> {
+        let res = self.inner.read_timeout(timeout)?;
+        self.log(Direction::Received, &res)
+            .map_err(TimeoutError::Other)?;
+        Ok(res)
+    }
+
+    // Only the exchange of commands is traced
+    fn input_reports(&self) -> Option<kanal::Receiver<[u8; 64]>> {
+        self.inner.input_reports()
+    }
+
+    fn notifications(&self) -> Option<kanal::Receiver<[u8; 64]>> {
+        self.inner.notifications()
+    }
+}
+
+/// Transport that stands in for a controller by serving the reports of a
+/// recorded trace.
+///
+/// Each report written must match the next one sent in the trace. The reports
+/// received after it in the trace are then queued for reading, as they would
+/// be by the controller, and reads time out once the queue is empty.
+pub struct Replay {
+    entries: Mutex<VecDeque<Entry>>,
+    pending: Mutex<VecDeque<[u8; 64]>>,
+    variant: Variant,
+}
+
+impl Replay {
+    pub fn open(path: &Path) -> eyre::Result<Replay> {
+        let (header, entries) = load(path)?;
+        let variant = Variant::from_product_id(header.product_id).ok_or_else(|| {
+            eyre::eyre!(
+                "trace is of an unknown controller ({:#06x})",
+                header.product_id
+            )
+        })?;
+        Ok(Replay {
+            entries: Mutex::new(entries.into()),
+            pending: Mutex::new(VecDeque::new()),
+            variant,
+        })
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Whether every report in the trace has been used.
+    pub fn is_finished(&self) -> bool {
+        self.entries.lock().is_empty() && self.pending.lock().is_empty()
+    }
+}
+
+impl Transport for Replay {
+    fn write(&self, data: &[u8]) -> eyre::Result<()> {
+        let mut entries = self.entries.lock();
+        let mut pending = self.pending.lock();
+
+        // Reports the controller sent before this one was written
+        queue_received(&mut entries, &mut pending);
+
+        let Some(expected) = entries.pop_front() else {
+            bail!("trace ended, but {} was sent", protocol::annotate(data));
+        };
+        if expected.direction != Direction::Sent || expected.data != data {
+            bail!(
+                "replay diverged from the trace: expected {}, but {} was sent",
+                match expected.direction {
+                    Direction::Sent => protocol::annotate(&expected.data),
+                    Direction::Received => "a reply to be read".to_owned(),
+                },
+                protocol::annotate(data)
+            );
+        }
+
+        queue_received(&mut entries, &mut pending);
+
+        Ok(())
+    }
+
+    fn read_timeout(&self, _timeout: Duration) -> Result<[u8; 64], TimeoutError> {
+        self.pending.lock().pop_front().ok_or(TimeoutError::Timeout)
+    }
+}
+
+/// Moves the received reports at the front of `entries` to `pending`.
+fn queue_received(entries: &mut VecDeque<Entry>, pending: &mut VecDeque<[u8; 64]>) {
+    while let Some(entry) = entries.front()
+        && entry.direction == Direction::Received
+    {
+        let mut report = [0; 64];
+        let len = entry.data.len().min(64);
+        report[..len].copy_from_slice(&entry.data[..len]);
+        pending.push_back(report);
+        entries.pop_front();
+    }
+}
@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+/// A channel for exchanging 64-byte reports with a controller.
+///
+/// Implemented by the HID device, and by wrappers that change how reports
+/// reach it. Transports must be usable from any thread, so that a
+/// [`Cyclone2`](crate::driver::Cyclone2) can be.
+pub trait Transport: Send + Sync {
+    fn write(&self, data: &[u8]) -> eyre::Result<()>;
+
+    fn read_timeout(&self, timeout: Duration) -> Result<[u8; 64], TimeoutError>;
+
+    /// Receives the input reports the controller sends as it's used, if this
+    /// transport gets them. Each report goes to only one receiver.
+    fn input_reports(&self) -> Option<kanal::Receiver<[u8; 64]>> {
+        None
+    }
+
+    /// Receives the reports the controller sends unprompted, such as
+    /// `ProfileChanged`, if this transport gets them.
+    fn notifications(&self) -> Option<kanal::Receiver<[u8; 64]>> {
+        None
+    }
+}
+
+pub enum TimeoutError {
+    Timeout,
+    Other(eyre::Report),
+}
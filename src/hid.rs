@@ -1,158 +1,81 @@
-use std::marker::PhantomData;
-use std::ptr::{NonNull, null};
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::time::Duration;
-
-use eyre::{bail, eyre};
-use hidapi_sys::{
-    hid_close, hid_device, hid_error, hid_exit, hid_init, hid_open, hid_read, hid_read_error,
-    hid_read_timeout, hid_write,
-};
-use widestring::U32CStr;
-
-static IS_INIT: AtomicBool = AtomicBool::new(false);
-
-pub struct Hid {
-    // Most hid functions are not safe to use from different threads.
-    _not_send: PhantomData<*const ()>,
-}
-
-impl Hid {
-    pub fn new() -> eyre::Result<Hid> {
-        if IS_INIT.swap(true, Ordering::AcqRel) {
-            bail!("Multiple instances of Hid are not allowed");
-        }
-
-        if unsafe { hid_init() } < 0 {
-            bail!("failed to initialise hidapi");
-        }
-
-        Ok(Hid {
-            _not_send: PhantomData,
-        })
-    }
-
-    pub fn open<'a>(&'a self, vendor_id: u16, product_id: u16) -> eyre::Result<HidDevice<'a>> {
-        let device = unsafe { hid_open(vendor_id, product_id, null()) };
-
-        if device.is_null() {
-            return Err(eyre!(get_error(device)));
-        }
-
-        Ok(HidDevice {
-            _hid: PhantomData,
-            device,
-            reader_count: Box::leak(Box::new(AtomicUsize::new(0))).into(),
-        })
-    }
+//! Access to HID devices, through one of two backends with the same
+//! interface: the vendored hidapi library, or Linux's hidraw devices.
+
+use std::{error, fmt, io};
+
+#[cfg(not(any(feature = "hidapi", feature = "hidraw")))]
+compile_error!("either the `hidapi` or the `hidraw` feature must be enabled");
+#[cfg(all(feature = "hidraw", not(target_os = "linux")))]
+compile_error!("the `hidraw` feature is only supported on Linux");
+
+#[cfg(all(feature = "hidapi", not(feature = "hidraw")))]
+mod hidapi;
+#[cfg(all(feature = "hidraw", target_os = "linux"))]
+mod hidraw;
+
+#[cfg(all(feature = "hidapi", not(feature = "hidraw")))]
+pub use hidapi::*;
+#[cfg(all(feature = "hidraw", target_os = "linux"))]
+pub use hidraw::*;
+
+/// A HID device found by [`Hid::enumerate`].
+///
+/// A device with several top-level collections is listed once for each, with
+/// the same path.
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    /// Platform-specific path, to be passed to [`Hid::open_path`].
+    pub path: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub serial_number: Option<String>,
+    /// Device release number, in binary-coded decimal.
+    pub release_number: u16,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub usage_page: u16,
+    pub usage: u16,
+    /// USB interface number, if the device is a USB device.
+    pub interface_number: Option<i32>,
 }
 
-impl Drop for Hid {
-    fn drop(&mut self) {
-        if unsafe { hid_exit() } != 0 {
-            panic!("Failed to shutdown hid");
-        }
-        IS_INIT.store(false, Ordering::Release);
-    }
-}
-
-pub struct HidDevice<'a> {
-    _hid: PhantomData<&'a Hid>,
-    device: *mut hid_device,
-    reader_count: NonNull<AtomicUsize>,
-}
-
-impl<'a> HidDevice<'a> {
-    pub fn read(&self, buf: &mut [u8]) -> eyre::Result<usize> {
-        let res = unsafe { hid_read(self.device, buf.as_mut_ptr(), buf.len()) };
-        self.check_error(res)
-    }
-
-    pub fn write(&self, data: &[u8]) -> eyre::Result<usize> {
-        let res = unsafe { hid_write(self.device, data.as_ptr(), data.len()) };
-        self.check_error(res)
-    }
-
-    /// Returns a `HidReadDevice` that can be safely used to read from another
-    /// thread.
-    ///
-    /// All readers must be dropped before dropping the parent `HidDevice`.
-    pub fn reader(&self) -> HidReadDevice {
-        let reader_count = unsafe { &*self.reader_count.as_ptr() };
-        reader_count.fetch_add(1, Ordering::AcqRel);
-        HidReadDevice {
-            device: self.device,
-            reader_count: self.reader_count,
-        }
-    }
-
-    fn check_error(&self, res: i32) -> eyre::Result<usize> {
-        if res == -1 {
-            Err(eyre!(get_error(self.device)))
-        } else {
-            Ok(res as usize)
-        }
-    }
+#[derive(Debug)]
+pub enum HidError {
+    /// No device has the given IDs or path.
+    NotFound,
+    /// An argument can't be passed to the backend, such as a path
+    /// containing NUL.
+    InvalidArgument(&'static str),
+    /// hidapi failed, giving its description of why.
+    Backend {
+        operation: &'static str,
+        message: String,
+    },
+    /// A system call failed.
+    Io {
+        operation: &'static str,
+        source: io::Error,
+    },
 }
 
-impl Drop for HidDevice<'_> {
-    fn drop(&mut self) {
-        let reader_count = unsafe { &*self.reader_count.as_ptr() };
-        if reader_count.load(Ordering::Acquire) > 0 {
-            panic!("HidDevice cannot be dropped while there are active HidReadDevices");
+impl fmt::Display for HidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HidError::NotFound => f.write_str("no matching HID device found"),
+            HidError::InvalidArgument(reason) => write!(f, "invalid argument: {reason}"),
+            HidError::Backend { operation, message } => {
+                write!(f, "failed to {operation}: {message}")
+            }
+            HidError::Io { operation, source } => write!(f, "failed to {operation}: {source}"),
         }
-        unsafe { hid_close(self.device) };
     }
 }
 
-fn get_error(device: *mut hid_device) -> String {
-    let error = unsafe { hid_error(device) };
-    let error = unsafe { U32CStr::from_ptr_str(error.cast()) };
-    error.to_string_lossy()
-}
-
-pub struct HidReadDevice {
-    device: *mut hid_device,
-    reader_count: NonNull<AtomicUsize>,
-}
-
-unsafe impl Send for HidReadDevice {}
-
-impl HidReadDevice {
-    pub fn read(&self, buf: &mut [u8]) -> eyre::Result<usize> {
-        let res = unsafe { hid_read(self.device, buf.as_mut_ptr(), buf.len()) };
-        self.check_error(res)
-    }
-
-    pub fn read_timeout(&self, buf: &mut [u8], timeout: Duration) -> eyre::Result<usize> {
-        let res = unsafe {
-            hid_read_timeout(
-                self.device,
-                buf.as_mut_ptr(),
-                buf.len(),
-                timeout.as_millis().try_into()?,
-            )
-        };
-        self.check_error(res)
-    }
-
-    fn check_error(&self, res: i32) -> eyre::Result<usize> {
-        if res == -1 {
-            Err(eyre!(get_read_error(self.device)))
-        } else {
-            Ok(res as usize)
+impl error::Error for HidError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            HidError::Io { source, .. } => Some(source),
+            _ => None,
         }
     }
 }
-
-impl Drop for HidReadDevice {
-    fn drop(&mut self) {
-        unsafe { &*self.reader_count.as_ptr() }.fetch_sub(1, Ordering::AcqRel);
-    }
-}
-
-fn get_read_error(device: *mut hid_device) -> String {
-    let error = unsafe { hid_read_error(device) };
-    let error = unsafe { U32CStr::from_ptr_str(error.cast()) };
-    error.to_string_lossy()
-}
@@ -0,0 +1,303 @@
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hidapi_sys::{
+    HID_API_MAX_REPORT_DESCRIPTOR_SIZE, hid_close, hid_device, hid_device_info, hid_enumerate,
+    hid_error, hid_exit, hid_free_enumeration, hid_get_feature_report, hid_get_input_report,
+    hid_get_manufacturer_string, hid_get_product_string, hid_get_report_descriptor,
+    hid_get_serial_number_string, hid_init, hid_open_path, hid_read, hid_read_error,
+    hid_read_timeout, hid_send_feature_report, hid_set_nonblocking, hid_write, wchar_t,
+};
+use parking_lot::Mutex;
+use tracing::warn;
+use widestring::WideCStr;
+
+use super::{DeviceInfo, HidError};
+
+/// Number of `Hid`s alive. hidapi is initialised while there are any, and
+/// the lock is also held around calls that use hidapi's global state.
+static CONTEXT: Mutex<usize> = Mutex::new(0);
+
+/// Longest string read from a device, in characters.
+const MAX_STRING_LEN: usize = 256;
+
+/// A handle on the hidapi library, which stays initialised while any handles
+/// or devices opened through them exist.
+pub struct Hid {
+    _private: (),
+}
+
+impl Hid {
+    pub fn new() -> Result<Hid, HidError> {
+        let mut count = CONTEXT.lock();
+        if *count == 0 && unsafe { hid_init() } < 0 {
+            return Err(HidError::Backend {
+                operation: "initialise hidapi",
+                message: get_error(ptr::null_mut()),
+            });
+        }
+        *count += 1;
+
+        Ok(Hid { _private: () })
+    }
+
+    /// Lists the HID devices with the given IDs, where 0 matches any ID.
+    pub fn enumerate(&self, vendor_id: u16, product_id: u16) -> Result<Vec<DeviceInfo>, HidError> {
+        let _context = CONTEXT.lock();
+        let list = unsafe { hid_enumerate(vendor_id, product_id) };
+
+        let mut devices = Vec::new();
+        let mut info = list;
+        while let Some(i) = unsafe { info.as_ref() } {
+            devices.push(device_info(i));
+            info = i.next;
+        }
+        unsafe { hid_free_enumeration(list) };
+
+        Ok(devices)
+    }
+
+    /// Opens the first device with the given IDs.
+    pub fn open(&self, vendor_id: u16, product_id: u16) -> Result<HidDevice, HidError> {
+        let info = self
+            .enumerate(vendor_id, product_id)?
+            .into_iter()
+            .next()
+            .ok_or(HidError::NotFound)?;
+        self.open_path(&info.path)
+    }
+
+    /// Opens a device by the path given in its [`DeviceInfo`].
+    pub fn open_path(&self, path: &str) -> Result<HidDevice, HidError> {
+        let path =
+            CString::new(path).map_err(|_| HidError::InvalidArgument("path contains NUL"))?;
+
+        let _context = CONTEXT.lock();
+        let device = unsafe { hid_open_path(path.as_ptr()) };
+        if device.is_null() {
+            return Err(HidError::Backend {
+                operation: "open device",
+                message: get_error(device),
+            });
+        }
+
+        Ok(HidDevice {
+            handle: Arc::new(Handle {
+                device,
+                lock: Mutex::new(()),
+                read_lock: Mutex::new(()),
+                _hid: self.clone(),
+            }),
+        })
+    }
+}
+
+impl Clone for Hid {
+    fn clone(&self) -> Hid {
+        *CONTEXT.lock() += 1;
+        Hid { _private: () }
+    }
+}
+
+impl Drop for Hid {
+    fn drop(&mut self) {
+        let mut count = CONTEXT.lock();
+        *count -= 1;
+        // Panicking here would abort if the drop is part of unwinding
+        if *count == 0 && unsafe { hid_exit() } != 0 {
+            warn!("Failed to shut down hidapi");
+        }
+    }
+}
+
+fn device_info(info: &hid_device_info) -> DeviceInfo {
+    DeviceInfo {
+        path: unsafe { CStr::from_ptr(info.path) }
+            .to_string_lossy()
+            .into_owned(),
+        vendor_id: info.vendor_id,
+        product_id: info.product_id,
+        serial_number: wide_string(info.serial_number),
+        release_number: info.release_number,
+        manufacturer: wide_string(info.manufacturer_string),
+        product: wide_string(info.product_string),
+        usage_page: info.usage_page,
+        usage: info.usage,
+        interface_number: (info.interface_number >= 0).then_some(info.interface_number),
+    }
+}
+
+fn wide_string(string: *const wchar_t) -> Option<String> {
+    if string.is_null() {
+        return None;
+    }
+    let string = unsafe { WideCStr::from_ptr_str(string.cast()) };
+    Some(string.to_string_lossy())
+}
+
+/// An open device, which can be shared between threads.
+pub struct HidDevice {
+    handle: Arc<Handle>,
+}
+
+struct Handle {
+    device: *mut hid_device,
+    /// Held by calls other than reads.
+    lock: Mutex<()>,
+    /// Held by reads, which hidapi allows at the same time as other calls.
+    read_lock: Mutex<()>,
+    _hid: Hid,
+}
+
+// Every call on the device is made with one of the locks held
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe { hid_close(self.device) };
+    }
+}
+
+impl HidDevice {
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        self.reader().read(buf)
+    }
+
+    pub fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        let _lock = self.handle.lock.lock();
+        let res = unsafe { hid_write(self.handle.device, data.as_ptr(), data.len()) };
+        self.check_error(res, "write report")
+    }
+
+    /// Sends a feature report, whose first byte is the report ID.
+    pub fn send_feature_report(&self, data: &[u8]) -> Result<usize, HidError> {
+        let _lock = self.handle.lock.lock();
+        let res = unsafe { hid_send_feature_report(self.handle.device, data.as_ptr(), data.len()) };
+        self.check_error(res, "send feature report")
+    }
+
+    /// Reads the feature report whose ID is the first byte of `buf`.
+    pub fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        let _lock = self.handle.lock.lock();
+        let res =
+            unsafe { hid_get_feature_report(self.handle.device, buf.as_mut_ptr(), buf.len()) };
+        self.check_error(res, "get feature report")
+    }
+
+    /// Requests the input report whose ID is the first byte of `buf`, rather
+    /// than waiting for the device to send it.
+    pub fn get_input_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        let _lock = self.handle.lock.lock();
+        let res = unsafe { hid_get_input_report(self.handle.device, buf.as_mut_ptr(), buf.len()) };
+        self.check_error(res, "get input report")
+    }
+
+    /// Makes reads return 0 straight away when no report is waiting.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), HidError> {
+        let _lock = self.handle.lock.lock();
+        let res = unsafe { hid_set_nonblocking(self.handle.device, nonblocking.into()) };
+        self.check_error(res, "set blocking mode").map(|_| ())
+    }
+
+    pub fn manufacturer_string(&self) -> Result<String, HidError> {
+        self.get_string(hid_get_manufacturer_string, "get manufacturer string")
+    }
+
+    pub fn product_string(&self) -> Result<String, HidError> {
+        self.get_string(hid_get_product_string, "get product string")
+    }
+
+    pub fn serial_number_string(&self) -> Result<String, HidError> {
+        self.get_string(hid_get_serial_number_string, "get serial number")
+    }
+
+    pub fn report_descriptor(&self) -> Result<Vec<u8>, HidError> {
+        let mut buf = vec![0; HID_API_MAX_REPORT_DESCRIPTOR_SIZE as usize];
+        let _lock = self.handle.lock.lock();
+        let res =
+            unsafe { hid_get_report_descriptor(self.handle.device, buf.as_mut_ptr(), buf.len()) };
+        let len = self.check_error(res, "get report descriptor")?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// Returns a `HidReadDevice` that reads from the same device, such as on
+    /// another thread, without holding up other calls.
+    pub fn reader(&self) -> HidReadDevice {
+        HidReadDevice {
+            handle: self.handle.clone(),
+        }
+    }
+
+    fn get_string(
+        &self,
+        get: unsafe extern "C" fn(*mut hid_device, *mut wchar_t, usize) -> i32,
+        operation: &'static str,
+    ) -> Result<String, HidError> {
+        let mut buf = [0 as wchar_t; MAX_STRING_LEN + 1];
+        let _lock = self.handle.lock.lock();
+        let res = unsafe { get(self.handle.device, buf.as_mut_ptr(), MAX_STRING_LEN) };
+        self.check_error(res, operation)?;
+        Ok(wide_string(buf.as_ptr()).unwrap_or_default())
+    }
+
+    fn check_error(&self, res: i32, operation: &'static str) -> Result<usize, HidError> {
+        if res == -1 {
+            Err(HidError::Backend {
+                operation,
+                message: get_error(self.handle.device),
+            })
+        } else {
+            Ok(res as usize)
+        }
+    }
+}
+
+fn get_error(device: *mut hid_device) -> String {
+    let error = unsafe { hid_error(device) };
+    let error = unsafe { WideCStr::from_ptr_str(error.cast()) };
+    error.to_string_lossy()
+}
+
+pub struct HidReadDevice {
+    handle: Arc<Handle>,
+}
+
+impl HidReadDevice {
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        let _lock = self.handle.read_lock.lock();
+        let res = unsafe { hid_read(self.handle.device, buf.as_mut_ptr(), buf.len()) };
+        self.check_error(res)
+    }
+
+    pub fn read_timeout(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, HidError> {
+        let timeout = timeout
+            .as_millis()
+            .try_into()
+            .map_err(|_| HidError::InvalidArgument("timeout is too long"))?;
+        let _lock = self.handle.read_lock.lock();
+        let res =
+            unsafe { hid_read_timeout(self.handle.device, buf.as_mut_ptr(), buf.len(), timeout) };
+        self.check_error(res)
+    }
+
+    fn check_error(&self, res: i32) -> Result<usize, HidError> {
+        if res == -1 {
+            Err(HidError::Backend {
+                operation: "read report",
+                message: get_read_error(self.handle.device),
+            })
+        } else {
+            Ok(res as usize)
+        }
+    }
+}
+
+fn get_read_error(device: *mut hid_device) -> String {
+    let error = unsafe { hid_read_error(device) };
+    let error = unsafe { WideCStr::from_ptr_str(error.cast()) };
+    error.to_string_lossy()
+}
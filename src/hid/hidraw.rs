@@ -0,0 +1,420 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{DeviceInfo, HidError};
+
+const SYSFS_CLASS: &str = "/sys/class/hidraw";
+
+/// Has no state, but keeps the interface identical to the hidapi backend's,
+/// so that code written against one builds with the other.
+#[derive(Clone)]
+pub struct Hid {
+    _private: (),
+}
+
+impl Hid {
+    pub fn new() -> Result<Hid, HidError> {
+        Ok(Hid { _private: () })
+    }
+
+    /// Lists the HID devices with the given IDs, where 0 matches any ID, in
+    /// order of device number.
+    pub fn enumerate(&self, vendor_id: u16, product_id: u16) -> Result<Vec<DeviceInfo>, HidError> {
+        let entries = fs::read_dir(SYSFS_CLASS).map_err(|source| HidError::Io {
+            operation: "list hidraw devices",
+            source,
+        })?;
+
+        let mut nodes = Vec::new();
+        for entry in entries {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            let name = entry.file_name();
+            let Some(number) = name
+                .to_str()
+                .and_then(|n| n.strip_prefix("hidraw"))
+                .and_then(|n| n.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            nodes.push((number, entry.path()));
+        }
+        nodes.sort_by_key(|(number, _)| *number);
+
+        let mut devices = Vec::new();
+        for (_, sysfs) in nodes {
+            // Devices can disappear while listing them
+            let Some(node) = Node::read(&sysfs) else {
+                continue;
+            };
+            if (vendor_id != 0 && node.vendor_id != vendor_id)
+                || (product_id != 0 && node.product_id != product_id)
+            {
+                continue;
+            }
+            let descriptor = fs::read(sysfs.join("device/report_descriptor")).unwrap_or_default();
+            let mut usages = top_level_usages(&descriptor);
+            if usages.is_empty() {
+                usages.push((0, 0));
+            }
+            for (usage_page, usage) in usages {
+                devices.push(DeviceInfo {
+                    path: Path::new("/dev")
+                        .join(sysfs.file_name().unwrap())
+                        .to_string_lossy()
+                        .into_owned(),
+                    vendor_id: node.vendor_id,
+                    product_id: node.product_id,
+                    serial_number: Some(node.serial_number.clone()),
+                    release_number: node.release_number,
+                    manufacturer: Some(node.manufacturer.clone()),
+                    product: Some(node.product.clone()),
+                    usage_page,
+                    usage,
+                    interface_number: node.interface_number,
+                });
+            }
+        }
+
+        Ok(devices)
+    }
+
+    /// Opens the first device with the given IDs.
+    pub fn open(&self, vendor_id: u16, product_id: u16) -> Result<HidDevice, HidError> {
+        let info = self
+            .enumerate(vendor_id, product_id)?
+            .into_iter()
+            .next()
+            .ok_or(HidError::NotFound)?;
+        self.open_path(&info.path)
+    }
+
+    /// Opens a device by the path given in its [`DeviceInfo`], such as
+    /// `/dev/hidraw0`.
+    pub fn open_path(&self, path: &str) -> Result<HidDevice, HidError> {
+        let name = Path::new(path)
+            .file_name()
+            .ok_or(HidError::InvalidArgument("path is not a hidraw device"))?;
+        let sysfs = Path::new(SYSFS_CLASS).join(name);
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|source| match source.kind() {
+                io::ErrorKind::NotFound => HidError::NotFound,
+                _ => HidError::Io {
+                    operation: "open device",
+                    source,
+                },
+            })?;
+
+        Ok(HidDevice {
+            file: Arc::new(file),
+            sysfs,
+        })
+    }
+}
+
+/// What sysfs says about a hidraw device.
+struct Node {
+    vendor_id: u16,
+    product_id: u16,
+    serial_number: String,
+    release_number: u16,
+    manufacturer: String,
+    product: String,
+    interface_number: Option<i32>,
+}
+
+impl Node {
+    /// Reads the attributes of the device under `/sys/class/hidraw`.
+    fn read(sysfs: &Path) -> Option<Node> {
+        let hid = fs::canonicalize(sysfs.join("device")).ok()?;
+        let uevent = fs::read_to_string(hid.join("uevent")).ok()?;
+        let field = |name: &str| {
+            uevent
+                .lines()
+                .find_map(|l| l.strip_prefix(name)?.strip_prefix('='))
+                .unwrap_or_default()
+                .to_owned()
+        };
+
+        // Such as HID_ID=0003:00003537:0000101D
+        let id = field("HID_ID");
+        let mut id = id.split(':').map(|part| u32::from_str_radix(part, 16).ok());
+        let _bus = id.next()??;
+        let vendor_id = id.next()??.try_into().ok()?;
+        let product_id = id.next()??.try_into().ok()?;
+
+        let mut node = Node {
+            vendor_id,
+            product_id,
+            serial_number: field("HID_UNIQ"),
+            release_number: 0,
+            manufacturer: String::new(),
+            product: field("HID_NAME"),
+            interface_number: None,
+        };
+
+        // A USB device's HID devices are children of its interfaces, with
+        // the strings and numbers on the interface and the device above
+        let interface = hid.parent()?;
+        let usb = interface.parent()?;
+        if let Some(interface_number) = read_attribute(interface, "bInterfaceNumber")
+            && usb.join("idVendor").exists()
+        {
+            node.interface_number = i32::from_str_radix(&interface_number, 16).ok();
+            node.release_number = read_attribute(usb, "bcdDevice")
+                .and_then(|v| u16::from_str_radix(&v, 16).ok())
+                .unwrap_or(0);
+            node.manufacturer = read_attribute(usb, "manufacturer").unwrap_or_default();
+            node.product = read_attribute(usb, "product").unwrap_or_default();
+            node.serial_number = read_attribute(usb, "serial").unwrap_or_default();
+        }
+
+        Some(node)
+    }
+}
+
+fn read_attribute(dir: &Path, name: &str) -> Option<String> {
+    let value = fs::read_to_string(dir.join(name)).ok()?;
+    Some(value.trim_end().to_owned())
+}
+
+/// Finds the usage page and usage of each top-level collection in a report
+/// descriptor, which is how hidapi splits a device into several.
+fn top_level_usages(descriptor: &[u8]) -> Vec<(u16, u16)> {
+    let mut usages = Vec::new();
+    let mut usage_page = 0;
+    let mut usage = None;
+    let mut depth = 0usize;
+
+    let mut pos = 0;
+    while pos < descriptor.len() {
+        let prefix = descriptor[pos];
+        if prefix == 0xfe {
+            // Long item, whose data size is in the next byte
+            let size = descriptor.get(pos + 1).copied().unwrap_or(0) as usize;
+            pos += 3 + size;
+            continue;
+        }
+
+        let size = match prefix & 0x03 {
+            3 => 4,
+            size => size as usize,
+        };
+        let Some(data) = descriptor.get(pos + 1..pos + 1 + size) else {
+            break;
+        };
+        let value = data
+            .iter()
+            .rev()
+            .fold(0u32, |value, byte| (value << 8) | *byte as u32);
+        pos += 1 + size;
+
+        match prefix & 0xfc {
+            // Usage Page
+            0x04 => usage_page = value as u16,
+            // Usage, which can include its page in the upper half
+            0x08 if size == 4 => usage = Some(((value >> 16) as u16, value as u16)),
+            0x08 => usage = Some((usage_page, value as u16)),
+            // Collection
+            0xa0 => {
+                if depth == 0
+                    && let Some(usage) = usage
+                {
+                    usages.push(usage);
+                }
+                depth += 1;
+                usage = None;
+            }
+            // End Collection
+            0xc0 => {
+                depth = depth.saturating_sub(1);
+                usage = None;
+            }
+            // Input, Output and Feature end the local items
+            0x80 | 0x90 | 0xb0 => usage = None,
+            _ => {}
+        }
+    }
+
+    usages
+}
+
+/// An open device, which can be shared between threads.
+pub struct HidDevice {
+    file: Arc<File>,
+    /// The device's directory under `/sys/class/hidraw`.
+    sysfs: PathBuf,
+}
+
+impl HidDevice {
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        read(&self.file, buf)
+    }
+
+    pub fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        (&*self.file).write(data).map_err(|source| HidError::Io {
+            operation: "write report",
+            source,
+        })
+    }
+
+    /// Sends a feature report, whose first byte is the report ID.
+    pub fn send_feature_report(&self, data: &[u8]) -> Result<usize, HidError> {
+        // HIDIOCSFEATURE
+        let res = unsafe { self.ioctl(0x06, data.len(), data.as_ptr().cast_mut()) }?;
+        self.check_ioctl(res, "send feature report")
+    }
+
+    /// Reads the feature report whose ID is the first byte of `buf`.
+    pub fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        // HIDIOCGFEATURE
+        let res = unsafe { self.ioctl(0x07, buf.len(), buf.as_mut_ptr()) }?;
+        self.check_ioctl(res, "get feature report")
+    }
+
+    /// Requests the input report whose ID is the first byte of `buf`, rather
+    /// than waiting for the device to send it.
+    pub fn get_input_report(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        // HIDIOCGINPUT
+        let res = unsafe { self.ioctl(0x0a, buf.len(), buf.as_mut_ptr()) }?;
+        self.check_ioctl(res, "get input report")
+    }
+
+    /// Makes reads return 0 straight away when no report is waiting.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), HidError> {
+        let fd = self.file.as_raw_fd();
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        let new_flags = if nonblocking {
+            flags | libc::O_NONBLOCK
+        } else {
+            flags & !libc::O_NONBLOCK
+        };
+        if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, new_flags) } < 0 {
+            return Err(HidError::Io {
+                operation: "set blocking mode",
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn manufacturer_string(&self) -> Result<String, HidError> {
+        Ok(self.node()?.manufacturer)
+    }
+
+    pub fn product_string(&self) -> Result<String, HidError> {
+        Ok(self.node()?.product)
+    }
+
+    pub fn serial_number_string(&self) -> Result<String, HidError> {
+        Ok(self.node()?.serial_number)
+    }
+
+    pub fn report_descriptor(&self) -> Result<Vec<u8>, HidError> {
+        fs::read(self.sysfs.join("device/report_descriptor")).map_err(|source| HidError::Io {
+            operation: "get report descriptor",
+            source,
+        })
+    }
+
+    /// Returns a `HidReadDevice` that reads from the same device, such as on
+    /// another thread.
+    pub fn reader(&self) -> HidReadDevice {
+        HidReadDevice {
+            file: self.file.clone(),
+        }
+    }
+
+    fn node(&self) -> Result<Node, HidError> {
+        Node::read(&self.sysfs).ok_or(HidError::NotFound)
+    }
+
+    /// Makes one of the hidraw ioctls that take a buffer of any length.
+    unsafe fn ioctl(&self, nr: u32, len: usize, buf: *mut u8) -> Result<libc::c_int, HidError> {
+        // The length goes in the 14-bit size field, which starts at bit 16 on
+        // every architecture. A longer one would change the direction bits.
+        if len >= 1 << 14 {
+            return Err(HidError::InvalidArgument("report is too long"));
+        }
+        let request = libc::_IOWR::<[u8; 0]>(b'H'.into(), nr) | (len << 16) as libc::Ioctl;
+        Ok(unsafe { libc::ioctl(self.file.as_raw_fd(), request, buf) })
+    }
+
+    fn check_ioctl(&self, res: libc::c_int, operation: &'static str) -> Result<usize, HidError> {
+        if res < 0 {
+            Err(HidError::Io {
+                operation,
+                source: io::Error::last_os_error(),
+            })
+        } else {
+            Ok(res as usize)
+        }
+    }
+}
+
+fn read(mut file: &File, buf: &mut [u8]) -> Result<usize, HidError> {
+    match file.read(buf) {
+        Ok(len) => Ok(len),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(0),
+        Err(source) => Err(HidError::Io {
+            operation: "read report",
+            source,
+        }),
+    }
+}
+
+pub struct HidReadDevice {
+    file: Arc<File>,
+}
+
+impl HidReadDevice {
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, HidError> {
+        read(&self.file, buf)
+    }
+
+    /// Reads a report, returning 0 if none arrives within `timeout`.
+    pub fn read_timeout(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, HidError> {
+        let mut poll_fd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout
+            .as_millis()
+            .try_into()
+            .map_err(|_| HidError::InvalidArgument("timeout is too long"))?;
+        let res = unsafe { libc::poll(&mut poll_fd, 1, timeout) };
+
+        if res < 0 {
+            let source = io::Error::last_os_error();
+            if source.kind() == io::ErrorKind::Interrupted {
+                return Ok(0);
+            }
+            return Err(HidError::Io {
+                operation: "wait for report",
+                source,
+            });
+        }
+        if res == 0 {
+            return Ok(0);
+        }
+        if poll_fd.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0 {
+            // Set when the device is unplugged
+            return Err(HidError::Io {
+                operation: "read report",
+                source: io::ErrorKind::NotConnected.into(),
+            });
+        }
+
+        self.read(buf)
+    }
+}
@@ -1,2 +1,10 @@
+// Allows `#[derive(Layout)]` to refer to this crate as `::opengamesir`.
+extern crate self as opengamesir;
+
+pub mod capture;
+pub mod dissector;
 pub mod driver;
 pub mod hid;
+pub mod library;
+pub mod plan;
+pub mod snapshot;
@@ -0,0 +1,247 @@
+//! A local store of named control profiles, kept as one JSON file per profile
+//! under `$XDG_DATA_HOME/opengamesir/library`.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use eyre::{OptionExt, WrapErr, bail, ensure};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::driver::{ControlProfile, Layout, ProfileKind};
+
+/// A saved control profile.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(with = "crate::driver::hex")]
+    bytes: Vec<u8>,
+}
+
+impl Entry {
+    pub fn new(name: &str, profile: &ControlProfile) -> eyre::Result<Entry> {
+        let mut bytes = Vec::with_capacity(ControlProfile::SIZE);
+        profile.write(&mut bytes)?;
+        Entry::from_bytes(name, bytes)
+    }
+
+    /// Creates an entry from the encoding of a control profile.
+    pub fn from_bytes(name: &str, bytes: Vec<u8>) -> eyre::Result<Entry> {
+        check_name(name)?;
+        ProfileKind::Control.validate(&bytes)?;
+        Ok(Entry {
+            name: name.to_owned(),
+            tags: Vec::new(),
+            notes: String::new(),
+            bytes,
+        })
+    }
+
+    pub fn profile(&self) -> eyre::Result<ControlProfile> {
+        ControlProfile::read(&mut self.bytes.as_slice())
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+}
+
+pub struct Library {
+    dir: PathBuf,
+}
+
+impl Library {
+    /// Opens the library in the user's data directory.
+    pub fn open_default() -> eyre::Result<Library> {
+        let data_dir = dirs::data_dir().ok_or_eyre("could not determine the data directory")?;
+        Library::open(data_dir.join("opengamesir").join("library"))
+    }
+
+    /// Opens the library stored in `dir`, creating it if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> eyre::Result<Library> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).wrap_err_with(|| format!("failed to create {}", dir.display()))?;
+        Ok(Library { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Saves an entry. Fails if an entry with the same name exists, unless
+    /// `replace` is set.
+    pub fn save(&self, entry: &Entry, replace: bool) -> eyre::Result<()> {
+        check_name(&entry.name)?;
+        let path = self.path(&entry.name);
+        ensure!(
+            replace || !path.exists(),
+            "a profile named {} already exists",
+            entry.name
+        );
+        let json = serde_json::to_string_pretty(entry)?;
+        fs::write(&path, json).wrap_err_with(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn load(&self, name: &str) -> eyre::Result<Entry> {
+        check_name(name)?;
+        let path = self.path(name);
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == ErrorKind::NotFound => bail!("no profile named {name}"),
+            Err(e) => return Err(e).wrap_err_with(|| format!("failed to read {}", path.display())),
+        };
+        let entry: Entry = serde_json::from_str(&json)
+            .wrap_err_with(|| format!("{} is not a valid library entry", path.display()))?;
+        ProfileKind::Control
+            .validate(&entry.bytes)
+            .wrap_err_with(|| format!("{} holds an invalid profile", path.display()))?;
+        Ok(entry)
+    }
+
+    /// Returns every entry, sorted by name. Files that can't be loaded are
+    /// skipped with a warning, so that one bad file doesn't hide the rest.
+    pub fn list(&self) -> eyre::Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for file in fs::read_dir(&self.dir)? {
+            let path = file?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                warn!("Skipping {}, whose name is not valid UTF-8", path.display());
+                continue;
+            };
+            match self.load(name) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!("Skipping {}: {e:#}", path.display()),
+            }
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    pub fn delete(&self, name: &str) -> eyre::Result<()> {
+        check_name(name)?;
+        match fs::remove_file(self.path(name)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => bail!("no profile named {name}"),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.json"))
+    }
+}
+
+/// Names double as file names, so are limited to a safe set of characters.
+fn check_name(name: &str) -> eyre::Result<()> {
+    ensure!(!name.is_empty(), "profile names cannot be empty");
+    ensure!(
+        name.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            && !name.starts_with('.'),
+        "invalid profile name {name:?}, use letters, digits, '-', '_' and '.'"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn library() -> (TempDir, Library) {
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::open(dir.path().join("library")).unwrap();
+        (dir, library)
+    }
+
+    fn entry(name: &str, fill: u8) -> Entry {
+        let mut bytes = vec![fill; ControlProfile::SIZE];
+        // A valid name
+        bytes[..32].fill(0);
+        Entry::from_bytes(name, bytes).unwrap()
+    }
+
+    #[test]
+    fn save_load_and_delete() {
+        let (_dir, library) = library();
+        let mut saved = entry("fps", 1);
+        saved.tags.push("shooter".to_owned());
+        saved.notes = "Low dead zones".to_owned();
+        library.save(&saved, false).unwrap();
+
+        let loaded = library.load("fps").unwrap();
+        assert_eq!(loaded.name, "fps");
+        assert!(loaded.has_tag("shooter"));
+        assert_eq!(loaded.notes, "Low dead zones");
+        assert_eq!(loaded.bytes(), saved.bytes());
+
+        let err = library.save(&entry("fps", 2), false).unwrap_err();
+        assert_eq!(err.to_string(), "a profile named fps already exists");
+        library.save(&entry("fps", 2), true).unwrap();
+        assert_eq!(library.load("fps").unwrap().bytes()[32], 2);
+
+        library.delete("fps").unwrap();
+        let err = library.load("fps").unwrap_err();
+        assert_eq!(err.to_string(), "no profile named fps");
+        let err = library.delete("fps").unwrap_err();
+        assert_eq!(err.to_string(), "no profile named fps");
+    }
+
+    #[test]
+    fn list_is_sorted_and_skips_bad_files() {
+        let (_dir, library) = library();
+        library.save(&entry("racing", 1), false).unwrap();
+        library.save(&entry("fps", 2), false).unwrap();
+        fs::write(library.dir().join("broken.json"), "{").unwrap();
+        fs::write(library.dir().join("bad name.json"), "{}").unwrap();
+        fs::write(library.dir().join("notes.txt"), "not an entry").unwrap();
+
+        let names: Vec<_> = library
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["fps", "racing"]);
+    }
+
+    #[test]
+    fn names_are_checked() {
+        for name in ["fps", "FPS-2", "my_profile.v2"] {
+            check_name(name).unwrap();
+        }
+        for name in [
+            "",
+            "..",
+            ".hidden",
+            "a/b",
+            "a\\b",
+            "../escape",
+            "with space",
+            "é",
+        ] {
+            assert!(check_name(name).is_err(), "{name:?}");
+        }
+
+        let (_dir, library) = library();
+        assert!(library.load("../library").is_err());
+        assert!(library.delete("..").is_err());
+        let mut escaping = entry("fps", 1);
+        escaping.name = "../fps".to_owned();
+        assert!(library.save(&escaping, false).is_err());
+        assert!(Entry::from_bytes("a/b", entry("fps", 1).bytes().to_vec()).is_err());
+    }
+}
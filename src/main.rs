@@ -1,20 +1,170 @@
 #![feature(if_let_guard, try_blocks)]
 
+mod cli;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
 use clap::Parser;
-use eyre::bail;
-use opengamesir::driver::{Cyclone2, ProfileNum};
-use opengamesir::hid::Hid;
+use eyre::{OptionExt, WrapErr, bail, ensure};
+use opengamesir::driver::backup::Backup;
+use opengamesir::driver::{ProfileId, ProfileNum, registry};
+use opengamesir::library::Library;
+use opengamesir::plan::{Config, Plan};
+use opengamesir::snapshot::SnapshotStore;
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::EnvFilter;
 
+use crate::cli::library::LibraryAction;
+use crate::cli::{Connection, ProfileSource};
+
 #[derive(clap::Parser)]
+struct Args {
+    /// Print the reports that would change the controller instead of sending
+    /// them. The controller is still read from.
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Write every report exchanged with the controller to a trace file.
+    #[arg(long, global = true, value_name = "FILE")]
+    record: Option<PathBuf>,
+    /// Use the reports in a trace file instead of a controller.
+    #[arg(long, global = true, value_name = "FILE")]
+    replay: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
 enum Command {
     GetLightProfile,
-    GetProfile { profile_id: u8 },
+    GetProfile {
+        profile_id: u8,
+    },
     GetFirmwareVersion,
+    /// Reads or changes individual fields of a profile.
+    Profile {
+        /// Profile number (1-4), `shift` or `light`.
+        profile: ProfileId,
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+    /// Prints a profile's raw bytes next to the fields they encode.
+    Dump {
+        /// Profile number (1-4), `shift`, `light`, or a raw dump file.
+        source: ProfileSource,
+    },
+    /// Prints the fields that differ between two profiles.
+    Diff {
+        /// Profile number (1-4), `shift`, `light`, or a raw dump file.
+        old: ProfileSource,
+        /// Profile number (1-4), `shift`, `light`, or a raw dump file.
+        new: ProfileSource,
+    },
+    /// Copies a control profile to another slot.
+    Copy {
+        /// Profile number (1-4) or `shift` to copy from.
+        from: ProfileId,
+        /// Profile number (1-4) or `shift` to overwrite.
+        to: ProfileId,
+        /// New name for the copy.
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Exchanges two control profiles.
+    Swap {
+        /// Profile number (1-4) or `shift`.
+        a: ProfileId,
+        /// Profile number (1-4) or `shift`.
+        b: ProfileId,
+    },
+    /// Manages profiles saved by name on this computer.
+    Library {
+        #[command(subcommand)]
+        action: LibraryAction,
+    },
+    /// Sends a report given as hex bytes and prints the replies. Starts an
+    /// interactive console if no report is given.
+    Raw {
+        /// Report bytes, e.g. `0f 09`.
+        report: Vec<String>,
+        /// Milliseconds to wait for replies after each report.
+        #[arg(long, default_value_t = 300)]
+        timeout: u64,
+        /// Allow sending commands that change the controller, or that
+        /// aren't known.
+        #[arg(long)]
+        allow_writes: bool,
+    },
+    /// Prints the configuration reports in a usbmon or USBPcap capture, and
+    /// the profile reads and writes they make up.
+    DecodePcap {
+        /// A pcap or pcapng file.
+        file: PathBuf,
+        /// Only decode the device at this bus and address, e.g. `3.7`.
+        #[arg(long, value_parser = cli::capture::parse_device)]
+        device: Option<(u16, u16)>,
+        /// Print the bytes of each report.
+        #[arg(long)]
+        hex: bool,
+    },
+    /// Writes a Wireshark dissector for the controller's reports, generated
+    /// from the same tables as this tool's decoder.
+    WiresharkDissector {
+        /// File to write, e.g. in `~/.local/lib/wireshark/plugins`. Printed
+        /// if not given.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Runs the rumble motors, from 0 (off) to 255.
+    Vibrate {
+        left: u8,
+        right: u8,
+    },
+    /// Lists the snapshots taken before each write to the controller.
+    History,
+    /// Restores the profiles changed by the most recent command from their
+    /// snapshots.
+    Undo,
+    /// Brings the controller in line with a config file describing its
+    /// profiles, after showing what would change.
+    Apply {
+        config: PathBuf,
+        /// Only show what would change, exiting with an error if anything
+        /// would.
+        #[arg(long)]
+        check: bool,
+        /// Apply without asking for confirmation.
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Saves every profile, the firmware version and the active profile to an
+    /// archive.
+    Backup {
+        file: PathBuf,
+    },
+    /// Writes every profile in an archive back to the controller.
+    Restore {
+        file: PathBuf,
+        /// Restore even if the archive was taken from a different controller
+        /// variant or firmware version.
+        #[arg(long)]
+        force: bool,
+    },
 }
 
-fn main() -> eyre::Result<()> {
+#[derive(clap::Subcommand)]
+enum ProfileAction {
+    /// Prints a field, e.g. `left_stick.front_dead` or
+    /// `mappings[Cross].map[0]`.
+    Get { path: String },
+    /// Changes a field, e.g. `right_trigger.quick_trigger_start_value=10`.
+    Set { assignment: String },
+}
+
+fn main() -> eyre::Result<ExitCode> {
     color_eyre::install()?;
 
     tracing_subscriber::fmt()
@@ -25,13 +175,31 @@ fn main() -> eyre::Result<()> {
         )
         .init();
 
-    let command = Command::parse();
+    let Args {
+        dry_run,
+        record,
+        replay,
+        command,
+    } = Args::parse();
 
-    let hid = Hid::new()?;
-    let c2 = Cyclone2::connect(&hid)?;
+    let mut connection = Connection::new();
+    // Undoing shouldn't create a snapshot that the next undo would restore
+    if !matches!(command, Command::Undo) {
+        connection.record_snapshots();
+    }
+    if dry_run {
+        connection.dry_run();
+    }
+    if let Some(path) = record {
+        connection.record_to(path);
+    }
+    if let Some(path) = replay {
+        connection.replay_from(path);
+    }
 
     match command {
         Command::GetLightProfile => {
+            let c2 = connection.get()?;
             let profile = c2.get_light_profile();
             println!("{profile:#?}");
         }
@@ -43,15 +211,215 @@ fn main() -> eyre::Result<()> {
                 4 => ProfileNum::P4,
                 _ => bail!("invalid profile id: {profile_id}"),
             };
+            let c2 = connection.get()?;
             let profile = c2.get_control_profile(profile_num)?;
             println!("{profile:#?}");
         }
         Command::GetFirmwareVersion => {
+            let c2 = connection.get()?;
             let version = c2.get_firmware_version()?;
             println!("controller: {}", version.controller);
             println!("dongle:     {}", version.dongle);
         }
+        Command::Profile { profile, action } => {
+            let kind = profile.kind();
+            match action {
+                ProfileAction::Get { path } => {
+                    let c2 = connection.get()?;
+                    let bytes = c2.read_profile(profile, kind.size())?;
+                    let field = registry::resolve(kind.shape(), kind.size(), &path)?;
+                    if field.is_leaf() {
+                        println!("{}", field.format(&bytes)?);
+                    } else {
+                        for leaf in field.leaves() {
+                            println!("{} = {}", leaf.path, leaf.format(&bytes)?);
+                        }
+                    }
+                }
+                ProfileAction::Set { assignment } => {
+                    let (path, value) = assignment
+                        .split_once('=')
+                        .ok_or_eyre("expected an assignment of the form <path>=<value>")?;
+                    let field = registry::resolve(kind.shape(), kind.size(), path.trim())?;
+
+                    let c2 = connection.get()?;
+                    let old = c2.read_profile(profile, kind.size())?;
+                    let mut new = old.clone();
+                    field.set(&mut new, value)?;
+                    kind.validate(&new)?;
+
+                    c2.update_profile(profile, &old, &new)?;
+                    println!("{} = {}", field.path, field.format(&new)?);
+                }
+            }
+        }
+        Command::Dump { source } => {
+            let (kind, bytes) = source.load(&connection)?;
+            cli::dump::print(kind, &bytes);
+        }
+        Command::Diff { old, new } => {
+            cli::diff::print(old.load(&connection)?, new.load(&connection)?)?;
+        }
+        Command::Copy { from, to, name } => {
+            let c2 = connection.get()?;
+            c2.copy_profile(from, to, name.as_deref())?;
+            println!("Copied profile {from} to {to}");
+        }
+        Command::Swap { a, b } => {
+            let c2 = connection.get()?;
+            let retargeted = c2.swap_profiles(a, b)?;
+            println!("Swapped profiles {a} and {b}");
+            for id in retargeted {
+                println!("Updated shift_value of profile {id}");
+            }
+        }
+        Command::Library { action } => cli::library::run(action, &connection)?,
+        Command::Raw {
+            report,
+            timeout,
+            allow_writes,
+        } => {
+            let options = cli::raw::RawOptions {
+                timeout: Duration::from_millis(timeout),
+                allow_writes,
+            };
+            let c2 = connection.get()?;
+            if report.is_empty() {
+                cli::raw::repl(c2, &options)?;
+            } else {
+                let report = cli::raw::parse(&report.join(" "))?;
+                cli::raw::send(c2, &report, &options)?;
+            }
+        }
+        Command::DecodePcap { file, device, hex } => {
+            let options = cli::capture::DecodeOptions { device, hex };
+            cli::capture::decode(&file, &options)?;
+        }
+        Command::WiresharkDissector { output } => {
+            let lua = opengamesir::dissector::lua();
+            match output {
+                Some(path) => fs::write(&path, lua)
+                    .wrap_err_with(|| format!("failed to write {}", path.display()))?,
+                None => print!("{lua}"),
+            }
+        }
+        Command::Vibrate { left, right } => {
+            let c2 = connection.get()?;
+            c2.vibrate(left, right)?;
+        }
+        Command::History => {
+            for snapshot in SnapshotStore::open_default()?.list()? {
+                println!(
+                    "{}  profile {:<5}  {}",
+                    humantime::format_rfc3339_seconds(snapshot.time),
+                    snapshot.profile,
+                    snapshot.path.display()
+                );
+            }
+        }
+        Command::Undo => {
+            let snapshots = SnapshotStore::open_default()?;
+            let group = snapshots.latest_group()?;
+            ensure!(!group.is_empty(), "there are no snapshots to restore");
+
+            let c2 = connection.get()?;
+            // Restoring the earliest snapshot of a profile last leaves it as
+            // it was before the command
+            for snapshot in group.iter().rev() {
+                let bytes = snapshot.bytes()?;
+                let current = c2.read_profile(snapshot.profile, snapshot.profile.kind().size())?;
+                c2.update_profile(snapshot.profile, &current, &bytes)?;
+            }
+
+            let time = humantime::format_rfc3339_seconds(group[0].time);
+            let mut profiles = Vec::new();
+            for snapshot in &group {
+                if !profiles.contains(&snapshot.profile) {
+                    profiles.push(snapshot.profile);
+                }
+            }
+            let noun = if profiles.len() == 1 {
+                "profile"
+            } else {
+                "profiles"
+            };
+            let profiles: Vec<_> = profiles.iter().map(ToString::to_string).collect();
+            let profiles = format!("{noun} {}", profiles.join(", "));
+            if dry_run {
+                println!("Would restore {profiles} as it was at {time}");
+            } else {
+                for snapshot in &group {
+                    snapshots.remove(snapshot)?;
+                }
+                println!("Restored {profiles} as it was at {time}");
+            }
+        }
+        Command::Apply { config, check, yes } => {
+            let config = Config::load(&config)?;
+            let library = Library::open_default()?;
+            let c2 = connection.get()?;
+
+            let plan = Plan::compute(c2, &config, &library)?;
+            if plan.is_empty() {
+                println!("Controller is up to date");
+                return Ok(ExitCode::SUCCESS);
+            }
+            cli::plan::print(&plan);
+
+            if check {
+                return Ok(ExitCode::FAILURE);
+            }
+            if !yes && !confirm("Apply these changes?")? {
+                println!("Nothing changed");
+                return Ok(ExitCode::SUCCESS);
+            }
+            plan.apply(c2)?;
+            println!("Applied");
+        }
+        Command::Backup { file } => {
+            let c2 = connection.get()?;
+            let backup = c2.backup()?;
+            fs::write(&file, backup.to_json()?)
+                .wrap_err_with(|| format!("failed to write {}", file.display()))?;
+            println!(
+                "Saved {} profiles from {} (firmware {}/{}) to {}",
+                backup.profiles.len(),
+                c2.variant(),
+                backup.firmware.controller,
+                backup.firmware.dongle,
+                file.display()
+            );
+        }
+        Command::Restore { file, force } => {
+            let json = fs::read_to_string(&file)
+                .wrap_err_with(|| format!("failed to read {}", file.display()))?;
+            let backup = Backup::from_json(&json)?;
+
+            let c2 = connection.get()?;
+            let firmware = c2.get_firmware_version()?;
+            if let Err(e) = backup.check_compatible(c2.variant(), &firmware) {
+                if !force {
+                    return Err(e.wrap_err("refusing to restore, use --force to override"));
+                }
+                eprintln!("Warning: {e}");
+            }
+
+            c2.restore(&backup)?;
+            println!(
+                "Restored {} profiles, active profile is {}",
+                backup.profiles.len(),
+                backup.current_profile
+            );
+        }
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+fn confirm(prompt: &str) -> eyre::Result<bool> {
+    print!("{prompt} [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
 }
@@ -0,0 +1,203 @@
+//! Declarative descriptions of what a controller should hold, and the plans
+//! for getting it there.
+//!
+//! A config lists the profiles to manage and, optionally, which one should be
+//! active:
+//!
+//! ```toml
+//! active = "2"
+//!
+//! [profiles.1]
+//! library = "apex-aim"
+//!
+//! [profiles.2]
+//! file = "dumps/racing.bin"
+//! set = { "left_trigger.front_dead" = "5%", "mappings[Cross].map[0]" = "Circle" }
+//!
+//! [profiles.light.set]
+//! standby_time = 10
+//! ```
+//!
+//! Each profile starts from a library entry, a dump file, or what the device
+//! currently holds if neither is given, and then has the fields in `set`
+//! changed. Profiles that aren't listed are left alone.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use eyre::{WrapErr, bail, ensure};
+use serde::Deserialize;
+
+use crate::driver::registry::{self, Change};
+use crate::driver::{Cyclone2, ProfileId, ProfileKind, changed_ranges};
+use crate::library::Library;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub active: Option<ProfileId>,
+    #[serde(default)]
+    pub profiles: HashMap<ProfileId, ProfileConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// Name of a library entry to start from.
+    pub library: Option<String>,
+    /// Dump file to start from, relative to the config file.
+    pub file: Option<PathBuf>,
+    /// Values of individual fields, by path.
+    #[serde(default)]
+    pub set: BTreeMap<String, toml::Value>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> eyre::Result<Config> {
+        let text = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        let mut config: Config =
+            toml::from_str(&text).wrap_err_with(|| format!("invalid config {}", path.display()))?;
+
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        for profile in config.profiles.values_mut() {
+            if let Some(file) = &mut profile.file {
+                *file = base_dir.join(&*file);
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Returns the bytes that a profile should hold, given what it holds now.
+    fn desired(
+        &self,
+        id: ProfileId,
+        current: &[u8],
+        library: &Library,
+    ) -> eyre::Result<Option<Vec<u8>>> {
+        let Some(profile) = self.profiles.get(&id) else {
+            return Ok(None);
+        };
+        let kind = id.kind();
+
+        let mut bytes = match (&profile.library, &profile.file) {
+            (Some(_), Some(_)) => bail!("profile {id} sets both library and file"),
+            (Some(name), None) => {
+                ensure!(
+                    kind == ProfileKind::Control,
+                    "profile {id} cannot come from the library, which holds control profiles"
+                );
+                library.load(name)?.bytes().to_vec()
+            }
+            (None, Some(file)) => {
+                fs::read(file).wrap_err_with(|| format!("failed to read {}", file.display()))?
+            }
+            (None, None) => current.to_vec(),
+        };
+        ensure!(
+            bytes.len() == kind.size(),
+            "profile {id} should be {} bytes, but its source has {}",
+            kind.size(),
+            bytes.len()
+        );
+
+        for (path, value) in &profile.set {
+            let value = match value {
+                toml::Value::String(s) => s.clone(),
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Boolean(b) => b.to_string(),
+                _ => bail!("unsupported value for {path} in profile {id}: {value}"),
+            };
+            let field = registry::resolve(kind.shape(), kind.size(), path)?;
+            field
+                .set(&mut bytes, &value)
+                .wrap_err_with(|| format!("failed to set {path} in profile {id}"))?;
+        }
+
+        kind.validate(&bytes)
+            .wrap_err_with(|| format!("profile {id} would be invalid"))?;
+        Ok(Some(bytes))
+    }
+}
+
+/// Changes needed to bring a controller in line with a config.
+#[derive(Debug)]
+pub struct Plan {
+    pub profiles: Vec<ProfilePlan>,
+    /// Currently and desired active profiles, if they differ.
+    pub active: Option<(ProfileId, ProfileId)>,
+}
+
+#[derive(Debug)]
+pub struct ProfilePlan {
+    pub id: ProfileId,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+    pub changes: Vec<Change>,
+}
+
+impl ProfilePlan {
+    /// Runs of bytes that will be written.
+    pub fn ranges(&self) -> Vec<Range<usize>> {
+        changed_ranges(&self.old, &self.new)
+    }
+}
+
+impl Plan {
+    /// Reads the profiles named in `config` from the device and works out
+    /// what would change.
+    pub fn compute(c2: &Cyclone2, config: &Config, library: &Library) -> eyre::Result<Plan> {
+        let mut profiles = Vec::new();
+        for id in ProfileId::ALL {
+            if !config.profiles.contains_key(&id) {
+                continue;
+            }
+            let kind = id.kind();
+            let old = c2.read_profile(id, kind.size())?;
+            let Some(new) = config.desired(id, &old, library)? else {
+                continue;
+            };
+            if old == new {
+                continue;
+            }
+            let changes = registry::diff(kind.shape(), kind.size(), &old, &new);
+            profiles.push(ProfilePlan {
+                id,
+                old,
+                new,
+                changes,
+            });
+        }
+
+        let active = match config.active {
+            Some(desired) => {
+                ensure!(
+                    desired.kind() == ProfileKind::Control,
+                    "the active profile must be a control profile"
+                );
+                let current = c2.get_current_profile()?;
+                (current != desired).then_some((current, desired))
+            }
+            None => None,
+        };
+
+        Ok(Plan { profiles, active })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty() && self.active.is_none()
+    }
+
+    pub fn apply(&self, c2: &Cyclone2) -> eyre::Result<()> {
+        for profile in &self.profiles {
+            c2.update_profile(profile.id, &profile.old, &profile.new)?;
+        }
+        if let Some((_, desired)) = self.active {
+            c2.switch_profile(desired)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,266 @@
+//! Copies of profiles taken before they are overwritten, kept as raw dumps
+//! under `$XDG_DATA_HOME/opengamesir/snapshots`.
+//!
+//! Snapshot files are named `<unix millis>-<n>-<profile>.bin`. Snapshots
+//! taken through the same store, such as by one command, share the time the
+//! first was taken and are numbered in order, so that a command that writes
+//! several profiles can be undone as a whole. Files named
+//! `<unix millis>-<profile>.bin`, from before snapshots were grouped, are
+//! each a group of their own. Either can be passed anywhere a dump file is
+//! accepted.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use eyre::{OptionExt, WrapErr};
+use parking_lot::Mutex;
+
+use crate::driver::ProfileId;
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    /// When the first snapshot of the group was taken.
+    pub time: SystemTime,
+    /// Position within the group.
+    pub seq: u32,
+    pub profile: ProfileId,
+    pub path: PathBuf,
+}
+
+impl Snapshot {
+    pub fn bytes(&self) -> eyre::Result<Vec<u8>> {
+        fs::read(&self.path).wrap_err_with(|| format!("failed to read {}", self.path.display()))
+    }
+
+    fn parse(path: PathBuf) -> Option<Snapshot> {
+        let stem = path.file_stem()?.to_str()?;
+        let (millis, rest) = stem.split_once('-')?;
+        let (seq, profile) = match rest.split_once('-') {
+            Some((seq, profile)) => (seq.parse().ok()?, profile),
+            None => (0, rest),
+        };
+        Some(Snapshot {
+            time: UNIX_EPOCH + Duration::from_millis(millis.parse().ok()?),
+            seq,
+            profile: profile.parse().ok()?,
+            path,
+        })
+    }
+}
+
+/// The group that a store's snapshots are added to.
+#[derive(Debug)]
+struct Group {
+    millis: u64,
+    next: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+    /// Shared between clones, so that every snapshot taken through a store
+    /// joins the same group.
+    group: Arc<Mutex<Option<Group>>>,
+}
+
+impl SnapshotStore {
+    /// Opens the store in the user's data directory.
+    pub fn open_default() -> eyre::Result<SnapshotStore> {
+        let data_dir = dirs::data_dir().ok_or_eyre("could not determine the data directory")?;
+        Ok(SnapshotStore::open(
+            data_dir.join("opengamesir").join("snapshots"),
+        ))
+    }
+
+    /// Opens the store in `dir`, which is created when the first snapshot is
+    /// recorded.
+    pub fn open(dir: impl Into<PathBuf>) -> SnapshotStore {
+        SnapshotStore {
+            dir: dir.into(),
+            group: Arc::default(),
+        }
+    }
+
+    pub fn record(&self, profile: ProfileId, bytes: &[u8]) -> eyre::Result<Snapshot> {
+        fs::create_dir_all(&self.dir)
+            .wrap_err_with(|| format!("failed to create {}", self.dir.display()))?;
+
+        let mut group = self.group.lock();
+        let group = match &mut *group {
+            Some(group) => group,
+            None => {
+                let mut millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+                // Keep groups started within the same millisecond apart
+                let taken = self.list()?;
+                while taken
+                    .iter()
+                    .any(|s| s.time == UNIX_EPOCH + Duration::from_millis(millis))
+                {
+                    millis += 1;
+                }
+                group.insert(Group { millis, next: 0 })
+            }
+        };
+
+        let seq = group.next;
+        let path = self
+            .dir
+            .join(format!("{}-{seq}-{profile}.bin", group.millis));
+        fs::write(&path, bytes).wrap_err_with(|| format!("failed to write {}", path.display()))?;
+        group.next += 1;
+        Ok(Snapshot {
+            time: UNIX_EPOCH + Duration::from_millis(group.millis),
+            seq,
+            profile,
+            path,
+        })
+    }
+
+    /// Returns every snapshot, oldest first.
+    pub fn list(&self) -> eyre::Result<Vec<Snapshot>> {
+        let files = match fs::read_dir(&self.dir) {
+            Ok(files) => files,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).wrap_err_with(|| format!("failed to read {}", self.dir.display()));
+            }
+        };
+
+        let mut snapshots = Vec::new();
+        for file in files {
+            if let Some(snapshot) = Snapshot::parse(file?.path()) {
+                snapshots.push(snapshot);
+            }
+        }
+        snapshots.sort_by_key(|s| (s.time, s.seq));
+        Ok(snapshots)
+    }
+
+    /// Returns the most recent group of snapshots, in the order they were
+    /// taken. Restoring them in reverse undoes the command that took them.
+    pub fn latest_group(&self) -> eyre::Result<Vec<Snapshot>> {
+        let mut snapshots = self.list()?;
+        let Some(last) = snapshots.last() else {
+            return Ok(snapshots);
+        };
+        let time = last.time;
+        snapshots.retain(|s| s.time == time);
+        Ok(snapshots)
+    }
+
+    pub fn remove(&self, snapshot: &Snapshot) -> eyre::Result<()> {
+        fs::remove_file(&snapshot.path)
+            .wrap_err_with(|| format!("failed to remove {}", snapshot.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::driver::ProfileNum;
+
+    const P1: ProfileId = ProfileId::Num(ProfileNum::P1);
+    const P2: ProfileId = ProfileId::Num(ProfileNum::P2);
+
+    fn store() -> (TempDir, SnapshotStore) {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SnapshotStore::open(dir.path().join("snapshots"));
+        (dir, store)
+    }
+
+    fn file_name(snapshot: &Snapshot) -> &str {
+        snapshot.path.file_name().unwrap().to_str().unwrap()
+    }
+
+    #[test]
+    fn snapshots_of_one_store_share_a_group() {
+        let (_dir, store) = store();
+        let first = store.record(P1, &[1]).unwrap();
+        let second = store.clone().record(P2, &[2]).unwrap();
+
+        assert_eq!(first.time, second.time);
+        assert_eq!((first.seq, second.seq), (0, 1));
+        let millis = first.time.duration_since(UNIX_EPOCH).unwrap().as_millis();
+        assert_eq!(file_name(&first), format!("{millis}-0-1.bin"));
+        assert_eq!(file_name(&second), format!("{millis}-1-2.bin"));
+        assert_eq!(second.bytes().unwrap(), [2]);
+    }
+
+    #[test]
+    fn stores_opened_together_get_their_own_groups() {
+        let (dir, store) = store();
+        let first = store.record(P1, &[1]).unwrap();
+        let other = SnapshotStore::open(dir.path().join("snapshots"));
+        let second = other.record(P1, &[2]).unwrap();
+        assert!(second.time > first.time);
+    }
+
+    #[test]
+    fn list_orders_by_group_then_seq() {
+        let (_dir, store) = store();
+        fs::create_dir_all(&store.dir).unwrap();
+        for name in [
+            "2000-1-shift.bin",
+            "2000-0-2.bin",
+            // From before snapshots were grouped
+            "1000-light.bin",
+            "3000-0-light.bin",
+            "notes.txt",
+            "3000-0-9.bin",
+        ] {
+            fs::write(store.dir.join(name), []).unwrap();
+        }
+
+        let listed: Vec<_> = store.list().unwrap();
+        let names: Vec<_> = listed.iter().map(file_name).collect();
+        assert_eq!(
+            names,
+            [
+                "1000-light.bin",
+                "2000-0-2.bin",
+                "2000-1-shift.bin",
+                "3000-0-light.bin"
+            ]
+        );
+        assert_eq!(listed[2].profile, ProfileId::Shift);
+        assert_eq!(listed[2].seq, 1);
+    }
+
+    #[test]
+    fn latest_group_holds_the_last_command() {
+        let (dir, earlier) = store();
+        earlier.record(P1, &[1]).unwrap();
+
+        let store = SnapshotStore::open(dir.path().join("snapshots"));
+        assert_eq!(store.latest_group().unwrap().len(), 1);
+        store.record(P1, &[2]).unwrap();
+        store.record(P2, &[3]).unwrap();
+        store.record(P1, &[4]).unwrap();
+
+        let group = store.latest_group().unwrap();
+        let taken: Vec<_> = group
+            .iter()
+            .map(|s| (s.profile, s.bytes().unwrap()[0]))
+            .collect();
+        assert_eq!(taken, [(P1, 2), (P2, 3), (P1, 4)]);
+
+        for snapshot in &group {
+            store.remove(snapshot).unwrap();
+        }
+        let group = store.latest_group().unwrap();
+        assert_eq!(group.len(), 1);
+        assert_eq!(group[0].bytes().unwrap(), [1]);
+    }
+
+    #[test]
+    fn empty_store() {
+        let (_dir, store) = store();
+        assert!(store.list().unwrap().is_empty());
+        assert!(store.latest_group().unwrap().is_empty());
+    }
+}
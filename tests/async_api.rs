@@ -0,0 +1,65 @@
+//! Exercises the async interface against the simulator.
+
+#![cfg(feature = "async")]
+
+use futures_util::StreamExt;
+use opengamesir::driver::asynchronous::AsyncCyclone2;
+use opengamesir::driver::simulator::Simulator;
+use opengamesir::driver::{Cyclone2, ProfileId, ProfileNum, Variant};
+
+fn connect(simulator: &Simulator) -> AsyncCyclone2 {
+    AsyncCyclone2::new(Cyclone2::with_transport(
+        Box::new(simulator.clone()),
+        Variant::Wired,
+    ))
+}
+
+#[tokio::test]
+async fn commands() {
+    let simulator = Simulator::new();
+    let c2 = connect(&simulator);
+
+    assert_eq!(c2.get_firmware_version().await.unwrap().controller, "1.0.0");
+
+    c2.switch_profile(ProfileId::Num(ProfileNum::P3))
+        .await
+        .unwrap();
+    assert_eq!(
+        c2.get_current_profile().await.unwrap(),
+        ProfileId::Num(ProfileNum::P3)
+    );
+
+    c2.vibrate(10, 20).await.unwrap();
+    assert_eq!(simulator.vibration(), (10, 20));
+}
+
+#[tokio::test]
+async fn profile_round_trip() {
+    let simulator = Simulator::new();
+    let c2 = connect(&simulator);
+    let id = ProfileId::Num(ProfileNum::P2);
+
+    let bytes: Vec<u8> = (0..id.kind().size()).map(|i| i as u8).collect();
+    c2.write_profile_range(id, 0, bytes.clone()).await.unwrap();
+    assert_eq!(simulator.profile(id), bytes);
+    assert_eq!(c2.read_profile(id, bytes.len()).await.unwrap(), bytes);
+}
+
+#[tokio::test]
+async fn streams() {
+    let simulator = Simulator::new();
+    let c2 = connect(&simulator);
+    let mut inputs = Box::pin(c2.input_reports().unwrap());
+    let mut notifications = Box::pin(c2.notifications().unwrap());
+
+    let mut input = [0; 64];
+    input[0] = 0x12;
+    input[1] = 0xaa;
+    simulator.send_input(input);
+    let mut notification = [0; 64];
+    notification[..3].copy_from_slice(&[0x10, 0x0f, 0x30]);
+    simulator.send_notification(notification);
+
+    assert_eq!(inputs.next().await, Some(input));
+    assert_eq!(notifications.next().await, Some(notification));
+}
@@ -0,0 +1,130 @@
+//! Runs the generated Wireshark dissector against stand-ins for the parts of
+//! Wireshark's Lua API it uses, and checks that it labels reports the same
+//! way `annotate` does.
+
+use mlua::{Function, Lua};
+use opengamesir::dissector;
+use opengamesir::driver::protocol::{
+    DEVICE_PREFIX, HOST_PREFIX, INPUT_REPORT_ID, REPORT_SIZE, VIBRATION_MAGIC, annotate,
+};
+
+/// Wireshark's API, as far as the dissector uses it. Ranges check their
+/// bounds, as Wireshark's do.
+const WIRESHARK: &str = r#"
+base = { DEC = 10, HEX = 16 }
+ProtoField = setmetatable({}, {
+  __index = function()
+    return function(...) return { ... } end
+  end,
+})
+
+function Proto(name, description)
+  registered = { name = name, description = description }
+  function registered.register_heuristic() end
+  return registered
+end
+
+local Range = {}
+Range.__index = Range
+local function range(bytes, offset, length)
+  return setmetatable({ bytes = bytes, offset = offset, length = length }, Range)
+end
+function Range:len()
+  return self.length
+end
+function Range:uint()
+  local value = 0
+  for i = 1, self.length do
+    value = value * 256 + self.bytes[self.offset + i]
+  end
+  return value
+end
+Range.__call = function(self, offset, length)
+  offset = offset or 0
+  length = length or self.length - offset
+  assert(offset >= 0 and length >= 0 and offset + length <= self.length, "range out of bounds")
+  return range(self.bytes, self.offset + offset, length)
+end
+
+local Tree = {}
+Tree.__index = Tree
+function Tree:add()
+  return setmetatable({}, Tree)
+end
+function Tree:append_text() end
+
+function dissect(bytes)
+  local pinfo = { cols = {} }
+  registered.dissector(range(bytes, 0, #bytes), pinfo, setmetatable({}, Tree))
+  return pinfo.cols.info
+end
+"#;
+
+/// Loads the dissector, with a `dissect` function that returns the info
+/// column for a report.
+fn load() -> Lua {
+    let lua = Lua::new();
+    lua.load(WIRESHARK).exec().unwrap();
+    lua.load(dissector::lua()).exec().unwrap();
+    lua
+}
+
+/// Reports with every prefix and command, and a few sets of parameters.
+fn reports() -> Vec<Vec<u8>> {
+    let mut bodies = vec![
+        vec![0; REPORT_SIZE],
+        vec![0xff; REPORT_SIZE],
+        (0..REPORT_SIZE as u8).collect(),
+    ];
+    for profile in 0..8 {
+        let mut body: Vec<u8> = (0..REPORT_SIZE as u8).rev().collect();
+        body[2] = profile;
+        bodies.push(body);
+    }
+    let mut vibration = vec![0; REPORT_SIZE];
+    vibration[2..4].copy_from_slice(&VIBRATION_MAGIC);
+    vibration[4..6].copy_from_slice(&[10, 20]);
+    bodies.push(vibration);
+
+    let mut reports = Vec::new();
+    for prefix in [HOST_PREFIX, DEVICE_PREFIX] {
+        for id in 0..=u8::MAX {
+            for body in &bodies {
+                let mut report = body.clone();
+                report[..2].copy_from_slice(&[prefix, id]);
+                // Reports cut short, down to the command alone
+                for len in 2..8 {
+                    reports.push(report[..len].to_vec());
+                }
+                reports.push(report);
+            }
+        }
+    }
+    let mut input = vec![0; REPORT_SIZE];
+    input[0] = INPUT_REPORT_ID;
+    reports.push(input);
+    reports
+}
+
+#[test]
+fn dissector_matches_annotate() {
+    let lua = load();
+    let dissect: Function = lua.globals().get("dissect").unwrap();
+    for report in reports() {
+        let info: Option<String> = dissect
+            .call(report.clone())
+            .unwrap_or_else(|e| panic!("{report:02x?}: {e}"));
+        assert_eq!(info, Some(annotate(&report)), "{report:02x?}");
+    }
+}
+
+#[test]
+fn bad_magic_is_labelled() {
+    let lua = load();
+    let dissect: Function = lua.globals().get("dissect").unwrap();
+    let mut report = vec![0; REPORT_SIZE];
+    report[..2].copy_from_slice(&[HOST_PREFIX, 0x20]);
+    report[4..6].copy_from_slice(&[10, 20]);
+    let info: Option<String> = dissect.call(report).unwrap();
+    assert_eq!(info.as_deref(), Some("Vibration (bad magic)"));
+}
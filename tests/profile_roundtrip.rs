@@ -0,0 +1,481 @@
+//! Property tests checking that every profile type decodes to exactly what
+//! was encoded.
+
+use std::fmt::Debug;
+
+use opengamesir::driver::layout;
+use opengamesir::driver::{
+    Animation, AxisMapModule, AxisMapTarget, ButtonMapping, ControlProfile, CurvePoint,
+    DeadzoneModule, Flag, Frame, FunctionKeyConfig, KeyCode, Layout, LightProfile, MacroStep,
+    MotionConfig, Percent, ProfileKind, ResponseCurve, RgbColor, SensorActivation, StickConfig,
+    TriggerConfig, TurboModule,
+};
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+
+fn array<T: Debug, const N: usize>(
+    element: impl Strategy<Value = T>,
+) -> impl Strategy<Value = [T; N]> {
+    prop::collection::vec(element, N).prop_map(|v| v.try_into().unwrap())
+}
+
+fn key_code() -> impl Strategy<Value = KeyCode> {
+    any::<u8>().prop_map(KeyCode)
+}
+
+/// Any byte, since values read from a controller aren't range-checked.
+fn percent() -> impl Strategy<Value = Percent> {
+    any::<u8>().prop_map(|v| Percent::read(&mut [v].as_slice()).unwrap())
+}
+
+fn flag() -> impl Strategy<Value = Flag> {
+    any::<u8>().prop_map(Flag)
+}
+
+fn turbo_module() -> impl Strategy<Value = TurboModule> {
+    (flag(), any::<u8>()).prop_map(|(turbo_en, turbo_speed)| TurboModule {
+        turbo_en,
+        turbo_speed,
+    })
+}
+
+fn deadzone_module() -> impl Strategy<Value = DeadzoneModule> {
+    (flag(), percent(), percent(), percent(), percent()).prop_map(
+        |(dead_en, front_dead, back_dead, anti_front_dead, anti_back_dead)| DeadzoneModule {
+            dead_en,
+            front_dead,
+            back_dead,
+            anti_front_dead,
+            anti_back_dead,
+        },
+    )
+}
+
+fn response_curve() -> impl Strategy<Value = ResponseCurve> {
+    let point = (any::<u8>(), any::<u8>()).prop_map(|(original_data, target_data)| CurvePoint {
+        original_data,
+        target_data,
+    });
+    (flag(), any::<u8>(), any::<u8>(), array(point)).prop_map(
+        |(linear_module_en, linear_status, linear_data, linear_control_points)| ResponseCurve {
+            linear_module_en,
+            linear_status,
+            linear_data,
+            linear_control_points,
+        },
+    )
+}
+
+fn axis_map_module() -> impl Strategy<Value = AxisMapModule> {
+    // Bytes that aren't a known target read as `Unknown`
+    let target = any::<u8>().prop_map(|v| AxisMapTarget::read(&mut [v].as_slice()).unwrap());
+    (
+        (flag(), flag(), flag(), percent(), any::<u8>()),
+        (target, any::<u8>()),
+        array::<_, 5>(key_code()),
+    )
+        .prop_map(
+            |((map_en, x_flip, y_flip, axis_ratio, mouse_dpi), (map_index, map_cross), keys)| {
+                let [up, down, left, right, dead] = keys;
+                AxisMapModule {
+                    map_en,
+                    x_flip,
+                    y_flip,
+                    axis_ratio,
+                    mouse_dpi,
+                    map_index,
+                    map_cross,
+                    map_up_value: up,
+                    map_down_value: down,
+                    map_left_value: left,
+                    map_right_value: right,
+                    map_dead_value: dead,
+                }
+            },
+        )
+}
+
+fn button_mapping() -> impl Strategy<Value = ButtonMapping> {
+    (turbo_module(), any::<u8>(), array(key_code()), any::<u8>()).prop_map(
+        |(turbo_module, map_en, map, toggle_en)| ButtonMapping {
+            turbo_module,
+            map_en,
+            map,
+            toggle_en,
+        },
+    )
+}
+
+fn macro_step() -> impl Strategy<Value = MacroStep> {
+    (key_code(), any::<u16>(), any::<u16>()).prop_map(
+        |(step_data, step_hold_time, step_delay_time)| MacroStep {
+            step_data,
+            step_hold_time,
+            step_delay_time,
+        },
+    )
+}
+
+/// Function key configs whose final step has no delay, as the encoding
+/// can't hold one.
+fn function_key_config() -> impl Strategy<Value = FunctionKeyConfig> {
+    (
+        button_mapping(),
+        any::<u8>(),
+        any::<u16>(),
+        any::<u8>(),
+        array::<_, 30>(macro_step()),
+    )
+        .prop_map(
+            |(mapping, macro_open_status, macro_cycle_time, step_num, mut steps)| {
+                steps[29].step_delay_time = 0;
+                FunctionKeyConfig {
+                    mapping,
+                    macro_open_status,
+                    macro_cycle_time,
+                    step_num,
+                    steps,
+                }
+            },
+        )
+}
+
+fn trigger_config() -> impl Strategy<Value = TriggerConfig> {
+    (
+        (turbo_module(), deadzone_module(), response_curve()),
+        (any::<u8>(), array(key_code()), any::<u8>()),
+        (any::<u8>(), any::<u8>(), any::<u8>()),
+    )
+        .prop_map(
+            |(
+                (turbo_module, dead_module, linear_module),
+                (map_en, map, toggle_en),
+                (quick_trigger_status, quick_trigger_start_value, quick_trigger_end_value),
+            )| TriggerConfig {
+                turbo_module,
+                dead_module,
+                map_en,
+                map,
+                toggle_en,
+                quick_trigger_status,
+                quick_trigger_start_value,
+                quick_trigger_end_value,
+                linear_module,
+            },
+        )
+}
+
+fn stick_config() -> impl Strategy<Value = StickConfig> {
+    (
+        any::<u8>(),
+        any::<u8>(),
+        deadzone_module(),
+        response_curve(),
+        axis_map_module(),
+    )
+        .prop_map(
+            |(stick_en, stick_square, dead_module, linear_module, map_module)| StickConfig {
+                stick_en,
+                stick_square,
+                dead_module,
+                linear_module,
+                map_module,
+            },
+        )
+}
+
+fn motion_config() -> impl Strategy<Value = MotionConfig> {
+    let activation = any::<u8>().prop_map(|v| SensorActivation::read(&mut [v].as_slice()).unwrap());
+    (
+        activation,
+        key_code(),
+        any::<u8>(),
+        deadzone_module(),
+        response_curve(),
+        axis_map_module(),
+    )
+        .prop_map(
+            |(
+                sensor_profile_status,
+                sensor_quick_key_value,
+                active_axis,
+                dead_module,
+                linear_module,
+                map_module,
+            )| MotionConfig {
+                sensor_profile_status,
+                sensor_quick_key_value,
+                active_axis,
+                dead_module,
+                linear_module,
+                map_module,
+            },
+        )
+}
+
+/// Names that fit in the 32-byte field. They may hold NULs, but can't end
+/// with one, as trailing NULs are taken to be padding.
+fn profile_name() -> impl Strategy<Value = String> {
+    "(.{0,31}[^\0])?".prop_filter("name must fit in 32 bytes", |name| name.len() <= 32)
+}
+
+fn control_profile() -> impl Strategy<Value = ControlProfile> {
+    (
+        profile_name(),
+        array::<u8, 15>(any::<u8>()),
+        array(button_mapping()),
+        array(function_key_config()),
+        (trigger_config(), trigger_config()),
+        (stick_config(), stick_config()),
+        (motion_config(), motion_config()),
+    )
+        .prop_map(
+            |(
+                name,
+                fun_data,
+                mappings,
+                fn_mappings,
+                (left_trigger, right_trigger),
+                (left_stick, right_stick),
+                (aim_sensor, tilt_sensor),
+            )| {
+                let [
+                    left_motor_value,
+                    right_motor_value,
+                    lt_motor_value,
+                    rt_motor_value,
+                    profile_audio_en,
+                    audio_volume,
+                    audio_mixer,
+                    mic_mute,
+                    mic_sensitivity,
+                    shift_en,
+                    shift_value,
+                    dpad_diagonal_lock_en,
+                    xinput_abxy_change,
+                    switch_abxy_change,
+                    report_rates_gears,
+                ] = fun_data;
+                ControlProfile {
+                    name,
+                    left_motor_value,
+                    right_motor_value,
+                    lt_motor_value,
+                    rt_motor_value,
+                    profile_audio_en,
+                    audio_volume,
+                    audio_mixer,
+                    mic_mute,
+                    mic_sensitivity,
+                    shift_en,
+                    shift_value,
+                    dpad_diagonal_lock_en,
+                    xinput_abxy_change,
+                    switch_abxy_change,
+                    report_rates_gears,
+                    mappings,
+                    fn_mappings,
+                    left_trigger,
+                    right_trigger,
+                    left_stick,
+                    right_stick,
+                    aim_sensor,
+                    tilt_sensor,
+                }
+            },
+        )
+}
+
+fn rgb_color() -> impl Strategy<Value = RgbColor> {
+    (any::<u8>(), any::<u8>(), any::<u8>()).prop_map(|(red, green, blue)| RgbColor {
+        red,
+        green,
+        blue,
+    })
+}
+
+fn animation() -> impl Strategy<Value = Animation> {
+    let frame = array(rgb_color()).prop_map(|leds| Frame { leds });
+    (
+        any::<u8>(),
+        any::<u8>(),
+        any::<u8>(),
+        any::<u8>(),
+        array(frame),
+    )
+        .prop_map(
+            |(key_frame_count, effect_count, speed, brightness, frames)| Animation {
+                key_frame_count,
+                effect_count,
+                speed,
+                brightness,
+                frames,
+            },
+        )
+}
+
+fn light_profile() -> impl Strategy<Value = LightProfile> {
+    (
+        (0..=3u8, array(animation())),
+        (flag(), any::<u8>(), rgb_color()),
+        (flag(), any::<u8>(), any::<[u8; 7]>()),
+    )
+        .prop_map(
+            |(
+                (config_index, animations),
+                (audio_reactive_mode, user_effect_index, profile_led),
+                (raise_wake_up, standby_time, reserved_data),
+            )| LightProfile {
+                config_index,
+                animations,
+                audio_reactive_mode,
+                user_effect_index,
+                profile_led,
+                raise_wake_up,
+                standby_time,
+                reserved_data,
+            },
+        )
+}
+
+fn encode<T: Layout>(value: &T) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    value.write(&mut bytes).unwrap();
+    bytes
+}
+
+/// Checks that `value` encodes to `T::SIZE` bytes that decode back to it.
+fn check_roundtrip<T: Layout + PartialEq + Debug>(value: &T) -> Result<(), TestCaseError> {
+    let bytes = encode(value);
+    prop_assert_eq!(bytes.len(), T::SIZE);
+    let decoded = T::read(&mut bytes.as_slice()).unwrap();
+    prop_assert_eq!(&decoded, value);
+    Ok(())
+}
+
+#[test]
+fn encoded_sizes() {
+    assert_eq!(ControlProfile::SIZE, 680);
+    assert_eq!(LightProfile::SIZE, 635);
+    // 30 five-byte steps, less the final step's delay
+    assert_eq!(FunctionKeyConfig::SIZE, 159);
+}
+
+/// A blank slot holds zeros, which aren't valid values for some fields, such
+/// as the axis map target. It must still decode, so that it can be backed up
+/// and restored.
+#[test]
+fn blank_profiles_decode() {
+    let control = ControlProfile::read(&mut [0; ControlProfile::SIZE].as_slice()).unwrap();
+    assert_eq!(
+        control.left_stick.map_module.map_index,
+        AxisMapTarget::Unknown(0)
+    );
+    ProfileKind::Control
+        .validate(&[0; ControlProfile::SIZE])
+        .unwrap();
+    ProfileKind::Light
+        .validate(&[0; LightProfile::SIZE])
+        .unwrap();
+}
+
+#[test]
+fn unexpected_values_are_kept() {
+    let mut bytes = vec![0; ControlProfile::SIZE];
+    let mut view = ControlProfile::view_mut(&mut bytes);
+    let percent = Percent::read(&mut [150].as_slice()).unwrap();
+    view.left_stick()
+        .dead_module()
+        .front_dead()
+        .set(&percent)
+        .unwrap();
+    view.left_stick()
+        .map_module()
+        .map_en()
+        .set(&Flag(2))
+        .unwrap();
+    view.aim_sensor()
+        .sensor_profile_status()
+        .set(&SensorActivation::Unknown(9))
+        .unwrap();
+
+    let profile = ControlProfile::read(&mut bytes.as_slice()).unwrap();
+    assert_eq!(profile.left_stick.dead_module.front_dead.get(), 150);
+    // Only bit 0 turns a setting on
+    assert!(!profile.left_stick.map_module.map_en.is_on());
+    assert!(Flag(3).is_on());
+    assert_eq!(
+        profile.aim_sensor.sensor_profile_status,
+        SensorActivation::Unknown(9)
+    );
+    assert_eq!(encode(&profile), bytes);
+
+    // Text from the user is still checked
+    assert!("150".parse::<Percent>().is_err());
+    assert!("9".parse::<SensorActivation>().is_err());
+    assert!("2".parse::<Flag>().is_err());
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    /// Strings are read up to their trailing NULs, with invalid UTF-8
+    /// replaced rather than failing the read.
+    #[test]
+    fn strings_decode_any_bytes(bytes in any::<[u8; 32]>()) {
+        let name = layout::read_string(&mut bytes.as_slice(), 32).unwrap();
+        let end = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        prop_assert_eq!(&name, &String::from_utf8_lossy(&bytes[..end]));
+    }
+
+    #[test]
+    fn control_profile_roundtrip(profile in control_profile()) {
+        check_roundtrip(&profile)?;
+    }
+
+    #[test]
+    fn light_profile_roundtrip(profile in light_profile()) {
+        check_roundtrip(&profile)?;
+    }
+
+    #[test]
+    fn function_key_config_roundtrip(config in function_key_config()) {
+        check_roundtrip(&config)?;
+    }
+
+    #[test]
+    fn trigger_config_roundtrip(config in trigger_config()) {
+        check_roundtrip(&config)?;
+    }
+
+    #[test]
+    fn stick_config_roundtrip(config in stick_config()) {
+        check_roundtrip(&config)?;
+    }
+
+    #[test]
+    fn motion_config_roundtrip(config in motion_config()) {
+        check_roundtrip(&config)?;
+    }
+
+    /// The final step's delay isn't stored, so it reads back as 0 and
+    /// doesn't spill into whatever follows.
+    #[test]
+    fn last_macro_step_delay_is_dropped(
+        config in function_key_config(),
+        delay in 1..=u16::MAX,
+        next in function_key_config(),
+    ) {
+        let mut with_delay = config.clone();
+        with_delay.steps[29].step_delay_time = delay;
+        prop_assert_eq!(encode(&with_delay), encode(&config));
+
+        // Followed by another config, as FL1 is by FR1
+        let mut bytes = encode(&with_delay);
+        bytes.extend(encode(&next));
+        let decoded = <[FunctionKeyConfig; 2]>::read(&mut bytes.as_slice()).unwrap();
+        prop_assert_eq!(decoded[0].steps[29].step_delay_time, 0);
+        prop_assert_eq!(&decoded[0], &config);
+        prop_assert_eq!(&decoded[1], &next);
+    }
+}
@@ -0,0 +1,50 @@
+//! Checks that a `Cyclone2` can be shared between threads, with each command
+//! getting its own reply.
+
+use std::sync::Arc;
+use std::thread;
+
+use opengamesir::driver::simulator::Simulator;
+use opengamesir::driver::{Cyclone2, ProfileId, Variant};
+
+/// Bytes that differ between profiles, so that a reply to the wrong read
+/// shows up.
+fn pattern(id: ProfileId) -> Vec<u8> {
+    (0..id.kind().size())
+        .map(|i| (i as u8).wrapping_add(id.index()))
+        .collect()
+}
+
+#[test]
+fn cyclone2_is_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Cyclone2>();
+}
+
+#[test]
+fn concurrent_reads_get_their_own_replies() {
+    let simulator = Simulator::new();
+    for id in ProfileId::ALL {
+        simulator.set_profile(id, &pattern(id));
+    }
+    let c2 = Arc::new(Cyclone2::with_transport(
+        Box::new(simulator),
+        Variant::Wired,
+    ));
+
+    let threads: Vec<_> = ProfileId::ALL
+        .into_iter()
+        .map(|id| {
+            let c2 = c2.clone();
+            thread::spawn(move || {
+                for _ in 0..200 {
+                    let bytes = c2.read_profile(id, id.kind().size()).unwrap();
+                    assert_eq!(bytes, pattern(id), "profile {id}");
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+//! Records sessions against the simulator and replays them, as is done to
+//! turn traces of real controllers into regression tests.
+
+use std::fs;
+use std::path::PathBuf;
+
+use opengamesir::driver::simulator::Simulator;
+use opengamesir::driver::trace::{Recording, Replay};
+use opengamesir::driver::{Cyclone2, ProfileId, ProfileNum, Variant};
+
+/// A trace file that is removed when dropped.
+struct TempTrace(PathBuf);
+
+impl TempTrace {
+    fn new(name: &str) -> TempTrace {
+        let file = format!("opengamesir-{}-{name}.jsonl", std::process::id());
+        TempTrace(std::env::temp_dir().join(file))
+    }
+}
+
+impl Drop for TempTrace {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Records a session that reads and changes the controller, returning what
+/// was read.
+fn record(trace: &TempTrace) -> (String, Vec<u8>) {
+    let simulator = Simulator::new();
+    let id = ProfileId::Num(ProfileNum::P1);
+    let pattern: Vec<u8> = (0..id.kind().size()).map(|i| i as u8).collect();
+    simulator.set_profile(id, &pattern);
+
+    let recording = Recording::create(Box::new(simulator), Variant::Wired, &trace.0).unwrap();
+    let c2 = Cyclone2::with_transport(Box::new(recording), Variant::Wired);
+    session(&c2)
+}
+
+fn session(c2: &Cyclone2) -> (String, Vec<u8>) {
+    let id = ProfileId::Num(ProfileNum::P1);
+    let version = c2.get_firmware_version().unwrap().controller;
+    let bytes = c2.read_profile(id, id.kind().size()).unwrap();
+    let mut changed = bytes.clone();
+    changed[40] ^= 0xff;
+    c2.update_profile(id, &bytes, &changed).unwrap();
+    c2.switch_profile(ProfileId::Num(ProfileNum::P2)).unwrap();
+    (version, bytes)
+}
+
+#[test]
+fn replay_matches_recording() {
+    let trace = TempTrace::new("replay");
+    let recorded = record(&trace);
+
+    let replay = Replay::open(&trace.0).unwrap();
+    assert_eq!(replay.variant(), Variant::Wired);
+    let c2 = Cyclone2::with_transport(Box::new(replay), Variant::Wired);
+    assert_eq!(session(&c2), recorded);
+
+    // The whole trace was used
+    let err = c2.get_current_profile().unwrap_err();
+    assert!(err.to_string().contains("trace ended"), "{err}");
+}
+
+#[test]
+fn replay_rejects_a_different_session() {
+    let trace = TempTrace::new("diverge");
+    record(&trace);
+
+    let replay = Replay::open(&trace.0).unwrap();
+    let c2 = Cyclone2::with_transport(Box::new(replay), Variant::Wired);
+    let err = c2.get_current_profile().unwrap_err();
+    assert!(err.to_string().contains("diverged"), "{err}");
+}
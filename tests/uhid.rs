@@ -0,0 +1,343 @@
+//! Runs the driver end to end, through the HID backend and the kernel's
+//! hidraw driver, against virtual controllers created with Linux's UHID driver
+//! and served by the simulator.
+//!
+//! Creating UHID devices needs read and write access to `/dev/uhid`, which
+//! usually means root, so the tests are ignored by default. Run them with:
+//!
+//! ```sh
+//! sudo -E cargo test --test uhid -- --ignored
+//! ```
+//!
+//! They fail if `/dev/uhid` can't be opened. Only the virtual controllers are
+//! ever connected to, so a real controller that is plugged in is left alone.
+
+#![cfg(target_os = "linux")]
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use opengamesir::driver::simulator::Simulator;
+use opengamesir::driver::transport::Transport;
+use opengamesir::driver::{Cyclone2, ProfileId, ProfileNum, Variant};
+use opengamesir::hid::{DeviceInfo, Hid};
+use parking_lot::Mutex;
+
+const VENDOR_ID: u16 = 0x3537;
+/// Product name of the virtual controllers, which tells them apart from real
+/// ones.
+const VIRTUAL_NAME: &str = "GameSir Cyclone 2 (virtual)";
+const BUS_USB: u16 = 0x03;
+
+// Event types, from <linux/uhid.h>
+const UHID_DESTROY: u32 = 1;
+const UHID_STOP: u32 = 3;
+const UHID_OUTPUT: u32 = 6;
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+
+/// Size of `struct uhid_event`, whose largest member is the create request.
+const EVENT_SIZE: usize = 4376;
+const EIO: u16 = 5;
+
+/// The config interface: vendor usage page `0xFFF0`, taking 64-byte commands
+/// with report ID `0x0f` and answering with report ID `0x10`.
+///
+/// Written from the usage pages and report IDs in `C2_PROTOCOL.md`, not
+/// dumped from a controller.
+#[rustfmt::skip]
+const CONFIG_DESCRIPTOR: &[u8] = &[
+    0x06, 0xf0, 0xff, // Usage Page (0xFFF0)
+    0x09, 0x01,       // Usage (1)
+    0xa1, 0x01,       // Collection (Application)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xff, 0x00, //   Logical Maximum (255)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x3f,       //   Report Count (63)
+    0x85, 0x10,       //   Report ID (0x10)
+    0x09, 0x01,       //   Usage (1)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute)
+    0x85, 0x0f,       //   Report ID (0x0f)
+    0x09, 0x01,       //   Usage (1)
+    0x91, 0x02,       //   Output (Data, Variable, Absolute)
+    0xc0,             // End Collection
+];
+
+/// The gamepad interface: vendor usage page `0xFF00`, sending 64-byte input
+/// reports with report ID `0x12`.
+#[rustfmt::skip]
+const GAMEPAD_DESCRIPTOR: &[u8] = &[
+    0x06, 0x00, 0xff, // Usage Page (0xFF00)
+    0x09, 0x01,       // Usage (1)
+    0xa1, 0x01,       // Collection (Application)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xff, 0x00, //   Logical Maximum (255)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x3f,       //   Report Count (63)
+    0x85, 0x12,       //   Report ID (0x12)
+    0x09, 0x01,       //   Usage (1)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute)
+    0xc0,             // End Collection
+];
+
+/// The virtual controllers of different tests would be indistinguishable, so
+/// the tests run one at a time.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// A controller with both of its HID interfaces, existing until dropped.
+struct VirtualController {
+    _config: Interface,
+    _gamepad: Interface,
+}
+
+impl VirtualController {
+    /// Creates a controller served by `simulator`.
+    fn create(variant: Variant, simulator: Simulator) -> VirtualController {
+        // Created first, so that the config interface has to be picked out
+        // rather than being the first with the controller's IDs
+        let gamepad = Interface::create(variant, "input1", GAMEPAD_DESCRIPTOR, None);
+        let config = Interface::create(variant, "input0", CONFIG_DESCRIPTOR, Some(simulator));
+        VirtualController {
+            _config: config,
+            _gamepad: gamepad,
+        }
+    }
+}
+
+/// One UHID device, with a thread answering the kernel's events.
+struct Interface {
+    file: File,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Interface {
+    fn create(
+        variant: Variant,
+        phys: &str,
+        descriptor: &[u8],
+        simulator: Option<Simulator>,
+    ) -> Interface {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/uhid")
+            .unwrap_or_else(|e| panic!("couldn't open /dev/uhid: {e}"));
+
+        let mut event = event(UHID_CREATE2);
+        put(&mut event, 4, VIRTUAL_NAME.as_bytes());
+        put(
+            &mut event,
+            132,
+            format!("opengamesir-uhid/{phys}").as_bytes(),
+        );
+        put(&mut event, 260, &(descriptor.len() as u16).to_le_bytes());
+        put(&mut event, 262, &BUS_USB.to_le_bytes());
+        put(&mut event, 264, &u32::from(VENDOR_ID).to_le_bytes());
+        put(
+            &mut event,
+            268,
+            &u32::from(variant.product_id()).to_le_bytes(),
+        );
+        put(&mut event, 280, descriptor);
+        file.write_all(&event)
+            .expect("failed to create UHID device");
+
+        let events = file.try_clone().unwrap();
+        let thread = thread::spawn(move || serve(events, simulator));
+
+        Interface {
+            file,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Interface {
+    fn drop(&mut self) {
+        // Destroying the device stops it, which ends the thread
+        let _ = self.file.write_all(&event(UHID_DESTROY));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn event(event_type: u32) -> Vec<u8> {
+    let mut event = vec![0; EVENT_SIZE];
+    put(&mut event, 0, &event_type.to_le_bytes());
+    event
+}
+
+fn put(event: &mut [u8], offset: usize, bytes: &[u8]) {
+    event[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn u16_at(event: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(event[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(event: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(event[offset..offset + 4].try_into().unwrap())
+}
+
+/// Answers the kernel's events for a device until it is stopped, passing
+/// output reports to the simulator and its replies back as input reports.
+fn serve(mut file: File, simulator: Option<Simulator>) {
+    let mut event = vec![0; EVENT_SIZE];
+    loop {
+        match file.read(&mut event) {
+            Ok(len) if len >= 4 => {}
+            Ok(_) => continue,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => return,
+        }
+
+        match u32_at(&event, 0) {
+            UHID_STOP => return,
+            UHID_OUTPUT => {
+                let Some(simulator) = &simulator else {
+                    continue;
+                };
+                let size = (u16_at(&event, 4100) as usize).min(4096);
+                simulator.write(&event[4..4 + size]).unwrap();
+                while let Ok(reply) = simulator.read_timeout(Duration::ZERO) {
+                    let mut input = self::event(UHID_INPUT2);
+                    put(&mut input, 4, &(reply.len() as u16).to_le_bytes());
+                    put(&mut input, 6, &reply);
+                    file.write_all(&input).unwrap();
+                }
+            }
+            // Feature reports aren't part of the protocol, but the kernel
+            // waits for an answer to these
+            UHID_GET_REPORT => {
+                let mut reply = self::event(UHID_GET_REPORT_REPLY);
+                put(&mut reply, 4, &event[4..8]);
+                put(&mut reply, 8, &EIO.to_le_bytes());
+                file.write_all(&reply).unwrap();
+            }
+            UHID_SET_REPORT => {
+                let mut reply = self::event(UHID_SET_REPORT_REPLY);
+                put(&mut reply, 4, &event[4..8]);
+                put(&mut reply, 8, &EIO.to_le_bytes());
+                file.write_all(&reply).unwrap();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Connects to the virtual controller, waiting for the kernel to finish
+/// setting it up.
+fn connect(hid: &Hid) -> Cyclone2 {
+    let mut attempts = 0;
+    loop {
+        match Cyclone2::connect_matching(hid, is_virtual) {
+            Ok(c2) => return c2,
+            Err(e) if attempts == 100 => panic!("virtual controller never appeared: {e:?}"),
+            Err(_) => {
+                attempts += 1;
+                thread::sleep(Duration::from_millis(20));
+            }
+        }
+    }
+}
+
+fn is_virtual(info: &DeviceInfo) -> bool {
+    info.product.as_deref() == Some(VIRTUAL_NAME)
+}
+
+#[test]
+#[ignore = "needs /dev/uhid"]
+fn interfaces() {
+    let _lock = LOCK.lock();
+    let simulator = Simulator::new();
+    let _controller = VirtualController::create(Variant::Wired, simulator);
+    let hid = Hid::new().unwrap();
+
+    let mut attempts = 0;
+    let mut devices: Vec<DeviceInfo> = Vec::new();
+    while devices.len() < 2 && attempts < 100 {
+        thread::sleep(Duration::from_millis(20));
+        devices = hid
+            .enumerate(VENDOR_ID, Variant::Wired.product_id())
+            .unwrap();
+        devices.retain(is_virtual);
+        attempts += 1;
+    }
+    let mut usage_pages: Vec<_> = devices.iter().map(|d| d.usage_page).collect();
+    usage_pages.sort();
+    assert_eq!(usage_pages, [0xff00, 0xfff0]);
+
+    let config = devices.iter().find(|d| d.usage_page == 0xfff0).unwrap();
+    let device = hid.open_path(&config.path).unwrap();
+    assert_eq!(device.report_descriptor().unwrap(), CONFIG_DESCRIPTOR);
+    assert_eq!(device.product_string().unwrap(), VIRTUAL_NAME);
+}
+
+#[test]
+#[ignore = "needs /dev/uhid"]
+fn firmware_version() {
+    let _lock = LOCK.lock();
+    let simulator = Simulator::new();
+    let _controller = VirtualController::create(Variant::Wired, simulator);
+    let hid = Hid::new().unwrap();
+    let c2 = connect(&hid);
+
+    assert_eq!(c2.variant(), Variant::Wired);
+    let version = c2.get_firmware_version().unwrap();
+    assert_eq!(version.controller, "1.0.0");
+    assert_eq!(version.dongle, "1.0.0");
+}
+
+#[test]
+#[ignore = "needs /dev/uhid"]
+fn profile_round_trip() {
+    let _lock = LOCK.lock();
+    let simulator = Simulator::new();
+    let _controller = VirtualController::create(Variant::Wired, simulator.clone());
+    let hid = Hid::new().unwrap();
+    let c2 = connect(&hid);
+
+    let id = ProfileId::Num(ProfileNum::P2);
+    let size = id.kind().size();
+    let bytes: Vec<u8> = (0..size).map(|i| (i * 7) as u8).collect();
+    c2.write_profile_range(id, 0, &bytes).unwrap();
+    assert_eq!(simulator.profile(id), bytes);
+    assert_eq!(c2.read_profile(id, size).unwrap(), bytes);
+}
+
+#[test]
+#[ignore = "needs /dev/uhid"]
+fn switch_profile() {
+    let _lock = LOCK.lock();
+    let simulator = Simulator::new();
+    let _controller = VirtualController::create(Variant::Wired, simulator.clone());
+    let hid = Hid::new().unwrap();
+    let c2 = connect(&hid);
+
+    let id = ProfileId::Num(ProfileNum::P3);
+    c2.switch_profile(id).unwrap();
+    assert_eq!(simulator.current_profile(), id.index());
+    assert_eq!(c2.get_current_profile().unwrap(), id);
+}
+
+#[test]
+#[ignore = "needs /dev/uhid"]
+fn vibrate() {
+    let _lock = LOCK.lock();
+    let simulator = Simulator::new();
+    let _controller = VirtualController::create(Variant::Wired, simulator.clone());
+    let hid = Hid::new().unwrap();
+    let c2 = connect(&hid);
+
+    c2.vibrate(40, 200).unwrap();
+    // Nothing is sent back, so wait for the report to reach the simulator
+    c2.get_firmware_version().unwrap();
+    assert_eq!(simulator.vibration(), (40, 200));
+}
@@ -0,0 +1,121 @@
+//! Checks that updating a profile sends only the bytes that changed.
+
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use opengamesir::driver::simulator::Simulator;
+use opengamesir::driver::transport::{TimeoutError, Transport};
+use opengamesir::driver::{Cyclone2, ProfileId, ProfileNum, Variant, changed_ranges};
+use parking_lot::Mutex;
+
+/// Passes reports on to the simulator, keeping the profile bytes each
+/// `WriteProfile` sends.
+struct Logged {
+    simulator: Simulator,
+    written: Arc<Mutex<Vec<Range<usize>>>>,
+}
+
+impl Transport for Logged {
+    fn write(&self, data: &[u8]) -> eyre::Result<()> {
+        if data[..2] == [0x0f, 0x03] {
+            let offset = usize::from(u16::from_be_bytes([data[3], data[4]]));
+            self.written
+                .lock()
+                .push(offset..offset + usize::from(data[5]));
+        }
+        self.simulator.write(data)
+    }
+
+    fn read_timeout(&self, timeout: Duration) -> Result<[u8; 64], TimeoutError> {
+        self.simulator.read_timeout(timeout)
+    }
+}
+
+const ID: ProfileId = ProfileId::Num(ProfileNum::P1);
+
+fn pattern() -> Vec<u8> {
+    (0..ID.kind().size()).map(|i| i as u8).collect()
+}
+
+/// Connects to a simulator holding `device`, returning the profile byte
+/// ranges written.
+fn connect(device: &[u8]) -> (Cyclone2, Simulator, Arc<Mutex<Vec<Range<usize>>>>) {
+    let simulator = Simulator::new();
+    simulator.set_profile(ID, device);
+    let written = Arc::default();
+    let transport = Logged {
+        simulator: simulator.clone(),
+        written: Arc::clone(&written),
+    };
+    let c2 = Cyclone2::with_transport(Box::new(transport), Variant::Wired);
+    (c2, simulator, written)
+}
+
+#[test]
+fn only_changed_bytes_are_sent() {
+    let old = pattern();
+    let mut new = old.clone();
+    new[10] ^= 0xff;
+    new[11] ^= 0xff;
+    new[200] ^= 0xff;
+    // A run longer than a chunk
+    for byte in &mut new[300..400] {
+        *byte ^= 0xff;
+    }
+
+    // The controller holds other values between the changes, which must be
+    // left as they are
+    let mut device = old.clone();
+    device[12] = 0xaa;
+    device[199] = 0xbb;
+    device[250] = 0xcc;
+    let (c2, simulator, written) = connect(&device);
+    c2.update_profile(ID, &old, &new).unwrap();
+
+    let mut sent: Vec<usize> = written.lock().iter().cloned().flatten().collect();
+    sent.sort();
+    let changed: Vec<usize> = changed_ranges(&old, &new).into_iter().flatten().collect();
+    assert_eq!(sent, changed);
+
+    let mut expected = new.clone();
+    expected[12] = 0xaa;
+    expected[199] = 0xbb;
+    expected[250] = 0xcc;
+    assert_eq!(simulator.profile(ID), expected);
+}
+
+#[test]
+fn nothing_is_sent_without_changes() {
+    let old = pattern();
+    let (c2, _, written) = connect(&old);
+    c2.update_profile(ID, &old, &old).unwrap();
+    assert!(written.lock().is_empty());
+}
+
+#[test]
+fn write_hook_runs_once_per_update() {
+    let old = pattern();
+    let mut new = old.clone();
+    new[0] ^= 0xff;
+    new[100] ^= 0xff;
+    let (mut c2, _, _) = connect(&old);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&calls);
+    c2.set_write_hook(move |_, _| {
+        counted.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    });
+    c2.update_profile(ID, &old, &new).unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn changed_ranges_are_runs() {
+    assert_eq!(changed_ranges(&[0; 4], &[0; 4]), Vec::<Range<usize>>::new());
+    assert_eq!(
+        changed_ranges(&[0, 0, 0, 0, 0, 0], &[1, 1, 0, 1, 0, 1]),
+        vec![0..2, 3..4, 5..6]
+    );
+}